@@ -0,0 +1,70 @@
+//! Compares the allocations and time it takes to decode plates from a busy camera through a reader
+//! versus borrowing them straight out of the received bytes. Run with `cargo bench`.
+
+use speed_daemon::msg::{self, DeserializeMessage, SerializeMessage};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time;
+
+const MESSAGES: usize = 100_000;
+
+/// Counts every allocation made through it, so the benchmark can report allocations per message.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    // A camera reporting a steady stream of plates, framed the way they'd come off the wire.
+    let frames = (0..MESSAGES)
+        .map(|i| {
+            let plate = msg::Plate {
+                plate: format!("UN{:04}X", i % 10000),
+                timestamp: i as u32,
+            };
+            let mut frame = Vec::new();
+            plate.to_writer(&mut frame).unwrap();
+            frame
+        })
+        .collect::<Vec<_>>();
+
+    bench("Plate::from_reader", &frames, |frame| {
+        black_box(msg::Plate::from_reader(frame).unwrap());
+    });
+    bench("Plate::from_bytes", &frames, |frame| {
+        black_box(msg::Plate::from_bytes(frame).unwrap());
+    });
+    bench("PlateRef::from_bytes", &frames, |frame| {
+        black_box(msg::PlateRef::from_bytes(frame).unwrap());
+    });
+}
+
+/// Decode every frame, printing the allocations and time per message.
+fn bench(name: &str, frames: &[Vec<u8>], decode: impl Fn(&[u8])) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = time::Instant::now();
+    for frame in frames {
+        decode(frame);
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{name:<24} {:>6.2} allocations/msg {:>8.1} ns/msg",
+        allocations as f64 / frames.len() as f64,
+        elapsed.as_nanos() as f64 / frames.len() as f64,
+    );
+}
@@ -0,0 +1,245 @@
+/// Options for the parts of the format that differ between protocols. The default is the format
+/// used by the speed-daemon protocol, which only has unsigned integers up to u32, and gives the
+/// length of strings, byte arrays and sequences in a single byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    all_primitives: bool,
+    str_prefix: PrefixWidth,
+    bytes_prefix: PrefixWidth,
+    seq_prefix: PrefixWidth,
+}
+
+/// The width of a length prefix, which is big-endian like any other unsigned integer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PrefixWidth {
+    /// A u8, for lengths up to 255. This is the default.
+    #[default]
+    U8,
+    /// A u16, for lengths up to 65535.
+    U16,
+    /// A u32, for lengths up to 4294967295.
+    U32,
+}
+
+impl PrefixWidth {
+    /// The longest length the prefix can hold.
+    pub const fn max(self) -> usize {
+        match self {
+            PrefixWidth::U8 => u8::MAX as usize,
+            PrefixWidth::U16 => u16::MAX as usize,
+            PrefixWidth::U32 => u32::MAX as usize,
+        }
+    }
+}
+
+impl Config {
+    /// The speed-daemon format, which is also the default.
+    pub const fn new() -> Self {
+        Config {
+            all_primitives: false,
+            str_prefix: PrefixWidth::U8,
+            bytes_prefix: PrefixWidth::U8,
+            seq_prefix: PrefixWidth::U8,
+        }
+    }
+
+    /// Also support bools, signed integers, u64s and floats. Numbers are big-endian and fixed
+    /// width like the unsigned ones, and bools are a single byte that is either 0 or 1. Without
+    /// this they are an `Error::UnsupportedType`.
+    pub const fn with_all_primitives(mut self) -> Self {
+        self.all_primitives = true;
+        self
+    }
+
+    /// Whether bools, signed integers, u64s and floats are supported.
+    pub const fn all_primitives(&self) -> bool {
+        self.all_primitives
+    }
+
+    /// Use the same width for every length prefix.
+    pub const fn with_prefix(self, width: PrefixWidth) -> Self {
+        self.with_str_prefix(width)
+            .with_bytes_prefix(width)
+            .with_seq_prefix(width)
+    }
+
+    /// The width of the length prefix of strings, which is also used for chars.
+    pub const fn with_str_prefix(mut self, width: PrefixWidth) -> Self {
+        self.str_prefix = width;
+        self
+    }
+
+    /// The width of the length prefix of strings.
+    pub const fn str_prefix(&self) -> PrefixWidth {
+        self.str_prefix
+    }
+
+    /// The width of the length prefix of byte arrays, as serialized by `serde_bytes` and the like.
+    pub const fn with_bytes_prefix(mut self, width: PrefixWidth) -> Self {
+        self.bytes_prefix = width;
+        self
+    }
+
+    /// The width of the length prefix of byte arrays.
+    pub const fn bytes_prefix(&self) -> PrefixWidth {
+        self.bytes_prefix
+    }
+
+    /// The width of the length prefix of sequences, such as `Vec`s.
+    pub const fn with_seq_prefix(mut self, width: PrefixWidth) -> Self {
+        self.seq_prefix = width;
+        self
+    }
+
+    /// The width of the length prefix of sequences.
+    pub const fn seq_prefix(&self) -> PrefixWidth {
+        self.seq_prefix
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+use super::{
+    from_bytes, from_bytes_with_config, from_reader_with_config, to_bytes, to_bytes_with_config,
+    Error,
+};
+
+#[test]
+fn test_primitives_round_trip() {
+    let config = Config::new().with_all_primitives();
+    let value = (
+        true,
+        false,
+        (i8::MIN, i16::MIN, i32::MIN, i64::MIN),
+        (-1_i8, -1_i16, -1_i32, -1_i64),
+        (i8::MAX, i16::MAX, i32::MAX, i64::MAX),
+        (0_u64, u64::MAX),
+        (1.5_f32, -0.0_f64, f64::INFINITY),
+    );
+    let bytes = to_bytes_with_config(&value, config).unwrap();
+    assert_eq!(bytes.len(), 2 + 15 * 3 + 16 + 4 + 16);
+    assert_eq!(value, from_bytes_with_config(&bytes, config).unwrap());
+    assert_eq!(value, from_reader_with_config(&bytes[..], config).unwrap());
+}
+
+#[test]
+fn test_primitives_big_endian() {
+    let config = Config::new().with_all_primitives();
+    let value = (true, -2_i16, 0x0102030405060708_u64, 1.0_f32);
+    let expected = [
+        0x01, // true
+        0xff, 0xfe, // -2
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // u64
+        0x3f, 0x80, 0x00, 0x00, // 1.0
+    ];
+    assert_eq!(to_bytes_with_config(&value, config).unwrap(), expected);
+}
+
+#[test]
+fn test_nan_round_trip() {
+    let config = Config::new().with_all_primitives();
+    let bytes = to_bytes_with_config(&f64::NAN, config).unwrap();
+    assert!(from_bytes_with_config::<f64>(&bytes, config)
+        .unwrap()
+        .is_nan());
+}
+
+#[test]
+fn test_invalid_bool() {
+    let config = Config::new().with_all_primitives();
+    let err = from_bytes_with_config::<(u8, bool)>(&[0, 2], config).unwrap_err();
+    assert!(matches!(err.kind(), Error::ExpectedBool(2)));
+    assert_eq!(err.position().unwrap().offset, 1);
+}
+
+#[test]
+fn test_primitives_disabled() {
+    assert!(matches!(
+        to_bytes_with_config(&true, Config::new()),
+        Err(Error::UnsupportedType)
+    ));
+    assert!(matches!(
+        to_bytes_with_config(&-1_i32, Config::default()),
+        Err(Error::UnsupportedType)
+    ));
+    assert!(matches!(
+        from_bytes_with_config::<u64>(&[0; 8], Config::new())
+            .unwrap_err()
+            .kind(),
+        Error::UnsupportedType
+    ));
+}
+
+#[test]
+fn test_prefix_widths() {
+    let config = Config::new()
+        .with_str_prefix(PrefixWidth::U16)
+        .with_seq_prefix(PrefixWidth::U32);
+    let value = ("hi".to_string(), vec![7_u8]);
+    let expected = [
+        0x00, 0x02, // string length
+        b'h', b'i', //
+        0x00, 0x00, 0x00, 0x01, // array length
+        7,
+    ];
+    let bytes = to_bytes_with_config(&value, config).unwrap();
+    assert_eq!(bytes, expected);
+    assert_eq!(value, from_bytes_with_config(&bytes, config).unwrap());
+    assert_eq!(value, from_reader_with_config(&bytes[..], config).unwrap());
+
+    // The default is still a single byte for both.
+    assert_eq!(to_bytes(&value).unwrap(), [2, b'h', b'i', 1, 7]);
+    assert_eq!(value, from_bytes(&[2, b'h', b'i', 1, 7]).unwrap());
+}
+
+#[test]
+fn test_bytes_prefix() {
+    #[derive(serde::Serialize)]
+    struct Bytes<'a>(#[serde(with = "serde_bytes_ref")] &'a [u8]);
+
+    mod serde_bytes_ref {
+        pub fn serialize<S: serde::Serializer>(v: &&[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(v)
+        }
+    }
+
+    let config = Config::new().with_bytes_prefix(PrefixWidth::U16);
+    let bytes = to_bytes_with_config(&Bytes(&[1, 2]), config).unwrap();
+    assert_eq!(bytes, [0x00, 0x02, 1, 2]);
+    let value: &[u8] = from_bytes_with_config(&bytes, config).unwrap();
+    assert_eq!(value, [1, 2]);
+}
+
+#[test]
+fn test_prefix_limits() {
+    let long = "a".repeat(256);
+    assert!(matches!(to_bytes(&long), Err(Error::StringTooLong(255))));
+    assert!(matches!(
+        to_bytes(&vec![0_u8; 256]),
+        Err(Error::ArrayTooLong(255))
+    ));
+
+    let config = Config::new().with_prefix(PrefixWidth::U16);
+    let bytes = to_bytes_with_config(&long, config).unwrap();
+    assert_eq!(bytes[..2], [0x01, 0x00]);
+    assert_eq!(
+        long,
+        from_bytes_with_config::<String>(&bytes, config).unwrap()
+    );
+    assert!(matches!(
+        to_bytes_with_config(&vec![0_u8; 65536], config),
+        Err(Error::ArrayTooLong(65535))
+    ));
+}
+
+#[test]
+fn test_huge_prefix_from_reader() {
+    // A length that is far longer than the input fails rather than allocating it all up front.
+    let config = Config::new().with_prefix(PrefixWidth::U32);
+    let bytes = [0xff, 0xff, 0xff, 0xff, b'a'];
+    let err = from_reader_with_config::<_, String>(&bytes[..], config).unwrap_err();
+    assert!(matches!(err.kind(), Error::Io(_)));
+    let err = from_reader_with_config::<_, Vec<u8>>(&bytes[..], config).unwrap_err();
+    assert!(matches!(err.kind(), Error::Io(_)));
+}
@@ -0,0 +1,949 @@
+use std::io::Read;
+use std::str;
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, SeqAccess, VariantAccess, Visitor,
+};
+use serde::Deserialize;
+
+use super::config::{Config, PrefixWidth};
+use super::error::{Error, Position, Result};
+use super::read::{Bytes, Input, IoReader, SliceReader};
+
+/// Deserializes values from an [`Input`], keeping track of where it is so errors can point at
+/// the offending bytes.
+pub struct Deserializer<R> {
+    input: R,
+    config: Config,
+    // Bookkeeping so errors can say where they happened. The path is only built up as an error
+    // unwinds, so successful deserialization doesn't pay for it.
+    offset: usize,
+    id: Option<u8>,
+    root: Option<&'static str>,
+}
+
+// A single step in the path to the value being deserialized.
+enum Segment {
+    Field(&'static str),
+    Index(usize),
+}
+
+impl<R: Read> Deserializer<IoReader<R>> {
+    /// Deserialize from a reader, copying every string and byte array out of it.
+    pub fn from_reader(reader: R) -> Self {
+        Deserializer::new(IoReader::new(reader))
+    }
+}
+
+impl<'de> Deserializer<SliceReader<'de>> {
+    /// Strings and bytes are borrowed from the slice where possible, so `&str` and `&[u8]` can be
+    /// deserialized without allocating.
+    pub fn from_slice(slice: &'de [u8]) -> Self {
+        Deserializer::new(SliceReader::new(slice))
+    }
+}
+
+impl<R> Deserializer<R> {
+    /// Use a config other than the default.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn new(input: R) -> Self {
+        Deserializer {
+            input,
+            config: Config::default(),
+            offset: 0,
+            id: None,
+            root: None,
+        }
+    }
+}
+
+/// Deserialize a value from a reader, leaving anything after it unread.
+pub fn from_reader<'a, R: Read, T>(reader: R) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_reader_with_config(reader, Config::default())
+}
+
+/// Deserialize a value from a reader, using the config.
+pub fn from_reader_with_config<'a, R: Read, T>(reader: R, config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_reader(reader).with_config(config);
+    let t = T::deserialize(&mut deserializer).map_err(|err| deserializer.finish(err))?;
+    Ok(t)
+}
+
+/// Deserialize a value from a slice of bytes, borrowing from it where possible. Any bytes left
+/// over after the value are an `Error::TrailingBytes`.
+pub fn from_bytes<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_with_config(s, Config::default())
+}
+
+/// Deserialize a value from a slice of bytes, using the config.
+pub fn from_bytes_with_config<'a, T>(s: &'a [u8], config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_slice(s).with_config(config);
+    let t = T::deserialize(&mut deserializer).map_err(|err| deserializer.finish(err))?;
+    if deserializer.input.remaining().is_empty() {
+        Ok(t)
+    } else {
+        Err(deserializer.locate(Error::TrailingBytes))
+    }
+}
+
+/// Deserialize a value from the start of a slice of bytes, borrowing from it where possible.
+/// Returns the value along with how many bytes it took up, so whatever follows it can be
+/// deserialized next.
+pub fn take_from_bytes<'a, T>(s: &'a [u8]) -> Result<(T, usize)>
+where
+    T: Deserialize<'a>,
+{
+    take_from_bytes_with_config(s, Config::default())
+}
+
+/// Deserialize a value from the start of a slice of bytes, using the config.
+pub fn take_from_bytes_with_config<'a, T>(s: &'a [u8], config: Config) -> Result<(T, usize)>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_slice(s).with_config(config);
+    let t = T::deserialize(&mut deserializer).map_err(|err| deserializer.finish(err))?;
+    Ok((t, s.len() - deserializer.input.remaining().len()))
+}
+
+impl<R> Deserializer<R> {
+    // Attach the current position to an error.
+    fn locate(&self, err: Error) -> Error {
+        self.error_at(self.offset, err)
+    }
+
+    // Attach a position to an error, unless it already has one from deeper in the input.
+    fn error_at(&self, offset: usize, err: Error) -> Error {
+        if let Error::At(..) = err {
+            return err;
+        }
+        let position = Position {
+            offset,
+            id: self.id,
+            path: String::new(),
+        };
+        Error::At(Box::new(position), Box::new(err))
+    }
+
+    // Add the element an error occurred in to the front of its path.
+    fn nest(&mut self, err: Error, segment: Segment) -> Error {
+        let mut err = self.locate(err);
+        if let Error::At(position, _) = &mut err {
+            let segment = match segment {
+                Segment::Field(name) => format!(".{name}"),
+                Segment::Index(index) => format!("[{index}]"),
+            };
+            position.path.insert_str(0, &segment);
+        }
+        // Struct names are only useful at the root, otherwise the field name says it all.
+        self.root = None;
+        err
+    }
+
+    // Finish off the path of an error that made it all the way out.
+    fn finish(&mut self, err: Error) -> Error {
+        let mut err = self.locate(err);
+        if let Error::At(position, _) = &mut err {
+            match self.root.take() {
+                Some(name) => position.path.insert_str(0, name),
+                None if position.path.starts_with('.') => {
+                    position.path.remove(0);
+                }
+                None => (),
+            }
+        }
+        err
+    }
+}
+
+impl<'de, R: Input<'de>> Deserializer<R> {
+    // Fill the buffer from the input, keeping track of how far into the input we are.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.input.read_exact(buf)?;
+        self.offset += buf.len();
+        Ok(())
+    }
+
+    // Consume the first character in the input.
+    fn next_byte(&mut self) -> Result<u8> {
+        let mut buf: [u8; 1] = Default::default();
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    // Parse a length prefix of the given width.
+    fn parse_len(&mut self, width: PrefixWidth) -> Result<usize> {
+        Ok(match width {
+            PrefixWidth::U8 => self.parse_u8()? as usize,
+            PrefixWidth::U16 => self.parse_u16()? as usize,
+            PrefixWidth::U32 => self.parse_u32()? as usize,
+        })
+    }
+
+    // Parse a length prefixed array of bytes.
+    fn parse_bytes(&mut self, width: PrefixWidth) -> Result<Bytes<'de>> {
+        let len = self.parse_len(width)?;
+        let bytes = self.input.read_bytes(len)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    // Parse a length prefixed string of ASCII characters.
+    fn parse_ascii(&mut self) -> Result<Bytes<'de>> {
+        let start = self.offset;
+        let bytes = self.parse_bytes(self.config.str_prefix())?;
+        if !bytes.is_ascii() {
+            return Err(self.error_at(start, Error::ExpectedAsciiCharacter));
+        }
+        Ok(bytes)
+    }
+
+    // Parse a single u8.
+    fn parse_u8(&mut self) -> Result<u8> {
+        self.next_byte()
+    }
+
+    // Parse a big-endian encoded u16.
+    fn parse_u16(&mut self) -> Result<u16> {
+        let mut buf: [u8; 2] = Default::default();
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    // Parse a big-endian encoded u32.
+    fn parse_u32(&mut self) -> Result<u32> {
+        let mut buf: [u8; 4] = Default::default();
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    // Read the bytes of a primitive that is only supported when the config says so.
+    fn parse_primitive<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if !self.config.all_primitives() {
+            return Err(Error::UnsupportedType);
+        }
+        let mut buf = [0; N];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // Parse a bool, which must be a 0 or a 1.
+    fn parse_bool(&mut self) -> Result<bool> {
+        let start = self.offset;
+        match self.parse_primitive::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [byte] => Err(self.error_at(start, Error::ExpectedBool(byte))),
+        }
+    }
+}
+
+impl<'de, R: Input<'de>> de::Deserializer<'de> for &mut Deserializer<R> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::UnsupportedType)
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bool(self.parse_bool()?)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i8(i8::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i16(i16::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i32(i32::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i64(i64::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.parse_u8()?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(self.parse_u16()?)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.parse_u32()?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(u64::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f32(f32::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f64(f64::from_be_bytes(self.parse_primitive()?))
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // Parse a string, check that it is one character, call `visit_char`.
+        let start = self.offset;
+        let s = self.parse_ascii()?;
+        if s.len() != 1 {
+            return Err(self.error_at(start, Error::ExpectedSingleLengthString));
+        }
+        visitor.visit_char(s[0] as char)
+    }
+
+    // Strings are borrowed when reading from a slice. Readers can't lend out their bytes, so
+    // there `&str` is only supported by types that also accept an owned `String`.
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // ASCII is always valid UTF-8.
+        match self.parse_ascii()? {
+            Bytes::Borrowed(bytes) => visitor.visit_borrowed_str(str::from_utf8(bytes).unwrap()),
+            Bytes::Copied(bytes) => visitor.visit_string(String::from_utf8(bytes).unwrap()),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.parse_bytes(self.config.bytes_prefix())? {
+            Bytes::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Bytes::Copied(bytes) => visitor.visit_byte_buf(bytes),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let len = self.parse_len(self.config.seq_prefix())?;
+        visitor.visit_seq(LengthPrefix::new(self, len))
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(LengthPrefix::new(self, len))
+    }
+
+    // Tuple structs look just like tuples, with no length prefix.
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::UnsupportedType)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor
+            .visit_seq(LengthPrefix::fields(&mut *self, fields))
+            .map_err(|err| {
+                let err = self.locate(err);
+                self.root = Some(name);
+                err
+            })
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let start = self.offset;
+        let tag = self.parse_u8()?;
+        visitor.visit_enum(Enum::new(self, tag, start))
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::UnsupportedType)
+    }
+}
+
+struct LengthPrefix<'a, R> {
+    de: &'a mut Deserializer<R>,
+    count: usize,
+    index: usize,
+    fields: Option<&'static [&'static str]>,
+}
+
+impl<'a, R> LengthPrefix<'a, R> {
+    fn new(de: &'a mut Deserializer<R>, count: usize) -> Self {
+        LengthPrefix {
+            de,
+            count,
+            index: 0,
+            fields: None,
+        }
+    }
+
+    // The elements of a struct, which are its named fields.
+    fn fields(de: &'a mut Deserializer<R>, fields: &'static [&'static str]) -> Self {
+        LengthPrefix {
+            fields: Some(fields),
+            ..Self::new(de, fields.len())
+        }
+    }
+}
+
+// `SeqAccess` is provided to the `Visitor` to give it the ability to iterate
+// through elements of the sequence.
+impl<'de, R: Input<'de>> SeqAccess<'de> for LengthPrefix<'_, R> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.count == 0 {
+            return Ok(None);
+        }
+        self.count -= 1;
+        let segment = match self.fields {
+            Some(fields) => Segment::Field(fields[self.index]),
+            None => Segment::Index(self.index),
+        };
+        self.index += 1;
+
+        seed.deserialize(&mut *self.de)
+            .map(Some)
+            .map_err(|err| self.de.nest(err, segment))
+    }
+}
+
+struct Enum<'a, R> {
+    de: &'a mut Deserializer<R>,
+    tag: u8,
+    // Where the tag was read from, for pointing at it if it doesn't match a variant.
+    start: usize,
+}
+
+impl<'a, R> Enum<'a, R> {
+    fn new(de: &'a mut Deserializer<R>, tag: u8, start: usize) -> Self {
+        Enum { de, tag, start }
+    }
+}
+
+// `EnumAccess` is provided to the `Visitor` to identify which variant was tagged. The tag is the
+// variant's index, so any tag the variant identifier rejects is one the enum doesn't have.
+impl<'de, R: Input<'de>> EnumAccess<'de> for Enum<'_, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let index: de::value::U32Deserializer<Error> = u32::from(self.tag).into_deserializer();
+        let variant = seed.deserialize(index).map_err(|_| {
+            self.de
+                .error_at(self.start, Error::UnknownVariantTag(self.tag))
+        })?;
+        if self.de.id.is_none() {
+            self.de.id = Some(self.tag);
+        }
+        Ok((variant, self))
+    }
+}
+
+// `VariantAccess` is provided to the `Visitor` to read the contents of the variant, which are
+// encoded just like the equivalent struct or tuple.
+impl<'de, R: Input<'de>> VariantAccess<'de> for Enum<'_, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(LengthPrefix::new(self.de, len))
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(LengthPrefix::fields(self.de, fields))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_struct() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Test {
+        int: u32,
+        seq: Vec<u16>,
+        msg: Option<String>,
+    }
+    let bytes = [
+        0x00, 0x00, 0x05, 0x39, // 1337
+        3,    // array length
+        0x00, 0x00, // item 0
+        0x00, 0x01, // item 1
+        0x00, 0x02, // item 2
+        5,    // string length
+        b'h', b'e', b'l', b'l', b'o',
+    ];
+    let expected = Test {
+        int: 1337,
+        seq: vec![0, 1, 2],
+        msg: Some("hello".to_string()),
+    };
+    assert_eq!(expected, from_bytes(&bytes[..]).unwrap());
+}
+
+#[test]
+fn test_tuple() {
+    let bytes = [
+        0x00, 0x00, 0x05, 0x39, // 1337
+        3,    // array length
+        0x00, 0x00, // item 0
+        0x00, 0x01, // item 1
+        0x00, 0x02, // item 2
+        5,    // string length
+        b'h', b'e', b'l', b'l', b'o',
+    ];
+    let expected: (u32, Vec<u16>, String) = (1337, vec![0, 1, 2], "hello".to_string());
+    assert_eq!(expected, from_bytes(&bytes[..]).unwrap());
+}
+
+#[test]
+fn test_u32() {
+    let bytes = [0x00, 0x00, 0x05, 0x39];
+    let expected: u32 = 1337;
+    assert_eq!(expected, from_bytes(&bytes[..]).unwrap());
+}
+
+#[test]
+fn test_u32s() {
+    let bytes = [2, 0x00, 0x00, 0x05, 0x39, 0xca, 0xfe, 0xba, 0xbe];
+    let expected = vec![1337, 0xcafebabe];
+    assert_eq!(expected, from_bytes::<Vec<u32>>(&bytes[..]).unwrap());
+}
+
+#[test]
+fn test_enum() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    enum Test {
+        Unit,
+        Newtype(u16),
+        Tuple(u8, u8),
+        Struct { a: u8 },
+    }
+    let bytes = [0, 1, 0x00, 0x01, 2, 2, 3, 3, 4];
+    let expected = (
+        Test::Unit,
+        Test::Newtype(1),
+        Test::Tuple(2, 3),
+        Test::Struct { a: 4 },
+    );
+    assert_eq!(expected, from_bytes(&bytes[..]).unwrap());
+}
+
+#[test]
+fn test_enum_unknown_tag() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    enum Test {
+        Unit,
+    }
+    assert!(matches!(
+        from_bytes::<Test>(&[1]).unwrap_err().kind(),
+        Error::UnknownVariantTag(1)
+    ));
+}
+
+#[test]
+fn test_error_position() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Dispatcher {
+        roads: Vec<u16>,
+    }
+    #[derive(Deserialize, PartialEq, Debug)]
+    enum Test {
+        Camera,
+        Dispatcher(Dispatcher),
+    }
+    // The fourth road is cut off after its first byte.
+    let bytes = [1, 4, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00];
+    let err = from_bytes::<Test>(&bytes).unwrap_err();
+    assert!(matches!(err.kind(), Error::Io(_)));
+    let position = err.position().unwrap();
+    assert_eq!(position.offset, 8);
+    assert_eq!(position.id, Some(1));
+    assert_eq!(position.path, "Dispatcher.roads[3]");
+    assert_eq!(
+        err.to_string(),
+        "failed to fill whole buffer at byte 8 of message 0x01 (Dispatcher.roads[3])"
+    );
+}
+
+#[test]
+fn test_error_position_nested() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Inner {
+        name: String,
+    }
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Outer {
+        id: u8,
+        inner: Vec<Inner>,
+    }
+    let bytes = [7, 2, 1, b'a', 2, b'b', 0xff];
+    let err = from_bytes::<Outer>(&bytes).unwrap_err();
+    assert!(matches!(err.kind(), Error::ExpectedAsciiCharacter));
+    // Points at the start of the offending string.
+    let position = err.position().unwrap();
+    assert_eq!(position.offset, 4);
+    assert_eq!(position.id, None);
+    assert_eq!(position.path, "Outer.inner[1].name");
+}
+
+#[test]
+fn test_error_position_unknown_tag() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    enum Test {
+        Unit,
+        Other,
+    }
+    let err = from_bytes::<(u16, Test)>(&[0, 1, 2]).unwrap_err();
+    assert!(matches!(err.kind(), Error::UnknownVariantTag(2)));
+    assert_eq!(err.position().unwrap().offset, 2);
+    assert_eq!(err.position().unwrap().path, "[1]");
+    assert_eq!(
+        from_bytes::<u8>(&[1, 2]).unwrap_err().to_string(),
+        "trailing bytes at byte 1"
+    );
+}
+
+#[test]
+fn test_borrowed() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Test<'a> {
+        name: &'a str,
+        data: &'a [u8],
+    }
+    let bytes = [3, b'f', b'o', b'o', 2, 0xff, 0x00];
+    let test: Test = from_bytes(&bytes).unwrap();
+    assert_eq!(test.name, "foo");
+    assert_eq!(test.data, [0xff, 0x00]);
+    assert_eq!(test.name.as_ptr(), bytes[1..].as_ptr());
+
+    // Readers copy, so only owned strings work.
+    assert!(from_reader::<_, (String, Vec<u8>)>(&bytes[..]).is_ok());
+    assert!(from_reader::<_, &str>(&bytes[..]).is_err());
+}
+
+#[test]
+fn test_take_from_bytes() {
+    let bytes = [3, b'f', b'o', b'o', 0x00, 0x01, 0x02];
+    let (name, consumed) = take_from_bytes::<&str>(&bytes).unwrap();
+    assert_eq!((name, consumed), ("foo", 4));
+    assert_eq!(take_from_bytes::<u16>(&bytes[consumed..]).unwrap(), (1, 2));
+    assert!(take_from_bytes::<u16>(&bytes[consumed + 2..]).is_err());
+}
+
+#[test]
+fn test_tuple_struct() {
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Test(u8, u16);
+    assert_eq!(Test(1, 2), from_bytes(&[1, 0, 2]).unwrap());
+}
+
+#[test]
+#[allow(dead_code)]
+fn test_unsupported_types() {
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged)]
+    enum Any {
+        Int(u8),
+    }
+    let bytes = [0; 16];
+    assert!(matches!(
+        from_bytes::<Any>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<bool>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<i8>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<i16>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<i32>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<i64>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<u64>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<f32>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<f64>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<std::collections::HashMap<u8, u8>>(&bytes)
+            .unwrap_err()
+            .kind(),
+        Error::UnsupportedType
+    ));
+    assert!(matches!(
+        from_bytes::<de::IgnoredAny>(&bytes).unwrap_err().kind(),
+        Error::UnsupportedType
+    ));
+}
+
+// A small xorshift generator so the fuzz tests are reproducible.
+#[cfg(test)]
+struct Rng(u64);
+
+#[cfg(test)]
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.next() as usize % (max_len + 1);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+// Decode the input as every supported and unsupported type. None of them may panic.
+#[cfg(test)]
+#[allow(dead_code)]
+fn decode_all(bytes: &[u8]) {
+    #[derive(Deserialize)]
+    struct Struct {
+        a: u8,
+        b: Vec<u16>,
+        c: Option<String>,
+        d: (char, u32),
+    }
+    #[derive(Deserialize)]
+    struct TupleStruct(u16, String);
+    #[derive(Deserialize)]
+    enum Enum {
+        Unit,
+        Newtype(String),
+        Tuple(u8, Vec<u8>),
+        Struct { a: u32 },
+    }
+
+    let _ = from_bytes::<u8>(bytes);
+    let _ = from_bytes::<u16>(bytes);
+    let _ = from_bytes::<u32>(bytes);
+    let _ = from_bytes::<char>(bytes);
+    let _ = from_bytes::<String>(bytes);
+    let _ = from_bytes::<Vec<u8>>(bytes);
+    let _ = from_bytes::<Vec<String>>(bytes);
+    let _ = from_bytes::<Option<u32>>(bytes);
+    let _ = from_bytes::<()>(bytes);
+    let _ = from_bytes::<Struct>(bytes);
+    let _ = from_bytes::<TupleStruct>(bytes);
+    let _ = from_bytes::<Vec<Enum>>(bytes);
+    let _ = from_bytes::<bool>(bytes);
+    let _ = from_bytes::<i32>(bytes);
+    let _ = from_bytes::<u64>(bytes);
+    let _ = from_bytes::<f64>(bytes);
+    let _ = from_bytes::<std::collections::HashMap<String, u8>>(bytes);
+    let _ = from_bytes::<de::IgnoredAny>(bytes);
+
+    let config = Config::new().with_all_primitives();
+    let _ = from_bytes_with_config::<(bool, i8, i16, i32, i64)>(bytes, config);
+    let _ = from_bytes_with_config::<Vec<(u64, f32, f64)>>(bytes, config);
+}
+
+#[test]
+fn test_fuzz_random_bytes() {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    for _ in 0..20_000 {
+        decode_all(&rng.bytes(64));
+    }
+}
+
+#[test]
+fn test_fuzz_truncated_input() {
+    let bytes = [
+        0x05, // a
+        0x02, 0x00, 0x01, 0x00, 0x02, // b
+        0x02, b'h', b'i', // c
+        0x01, b'x', 0xca, 0xfe, 0xba, 0xbe, // d
+    ];
+    for len in 0..bytes.len() {
+        decode_all(&bytes[..len]);
+    }
+    for len in 0..bytes.len() {
+        assert!(from_bytes::<(u8, Vec<u16>, String, (char, u32))>(&bytes[..len]).is_err());
+    }
+    assert!(from_bytes::<(u8, Vec<u16>, String, (char, u32))>(&bytes).is_ok());
+}
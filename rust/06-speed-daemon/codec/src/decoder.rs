@@ -0,0 +1,168 @@
+use serde::de::{Deserialize, DeserializeOwned};
+use std::io;
+use std::marker::PhantomData;
+
+use super::config::Config;
+use super::de::take_from_bytes_with_config;
+use super::error::{Error, Result};
+
+/// A push-style decoder for a stream of values. Bytes can be added in chunks of any size as they
+/// arrive, and values are only decoded once all of their bytes are available, so nothing is lost
+/// when a value is split across reads. Values are decoded straight out of the decoder's buffer,
+/// so they can borrow from it with [`Decoder::decode_as`].
+#[derive(Debug)]
+pub struct Decoder<T> {
+    buf: Vec<u8>,
+    // Where the next value starts. Decoded bytes are only dropped when more are pushed, so
+    // borrowed values stay valid until then.
+    start: usize,
+    config: Config,
+    marker: PhantomData<T>,
+}
+
+impl<T> Default for Decoder<T> {
+    fn default() -> Self {
+        Decoder {
+            buf: Vec::new(),
+            start: 0,
+            config: Config::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Decoder<T> {
+    /// A decoder with an empty buffer, using the default config.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A decoder with an empty buffer, using the config.
+    pub fn with_config(config: Config) -> Self {
+        Decoder {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Decode the next value, returning None if its bytes haven't all arrived yet. After an error
+    /// the buffer is left as is, since there's no telling where the next value starts.
+    pub fn decode(&mut self) -> Result<Option<T>> {
+        self.decode_as()
+    }
+}
+
+impl<T> Decoder<T> {
+    /// Add bytes to the end of the buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.drain(..self.start);
+        self.start = 0;
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The number of bytes waiting to be decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// The first byte of the next value, if it has arrived. For enums this is the tag, which says
+    /// what type to decode the value as.
+    pub fn peek(&self) -> Option<u8> {
+        self.buf.get(self.start).copied()
+    }
+
+    /// Decode the next value as some type other than `T`, borrowing from the buffer where
+    /// possible. Otherwise this is the same as [`Decoder::decode`].
+    pub fn decode_as<'a, U: Deserialize<'a>>(&'a mut self) -> Result<Option<U>> {
+        let input = &self.buf[self.start..];
+        if input.is_empty() {
+            return Ok(None);
+        }
+        match take_from_bytes_with_config(input, self.config) {
+            Ok((value, consumed)) => {
+                self.start += consumed;
+                Ok(Some(value))
+            }
+            Err(err) => match err.kind() {
+                Error::Io(io) if io.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+                _ => Err(err),
+            },
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_chunks() {
+    let bytes = [
+        0x00, 0x00, 0x05, 0x39, // 1337
+        3,    // array length
+        0x00, 0x00, // item 0
+        0x00, 0x01, // item 1
+        0x00, 0x02, // item 2
+        0x00, 0x00, 0x05, 0x39, // 1337
+        0,    // array length
+    ];
+    let mut decoder = Decoder::<(u32, Vec<u16>)>::new();
+    assert_eq!(decoder.decode().unwrap(), None);
+
+    // Feed one byte at a time, checking values only come out once they are complete.
+    let mut values = Vec::new();
+    for (i, byte) in bytes.iter().enumerate() {
+        decoder.push(&[*byte]);
+        if let Some(value) = decoder.decode().unwrap() {
+            values.push((i, value));
+        }
+    }
+    assert_eq!(
+        values,
+        vec![(10, (1337, vec![0, 1, 2])), (15, (1337, vec![]))]
+    );
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn test_multiple_values_in_one_chunk() {
+    let mut decoder = Decoder::<u16>::new();
+    decoder.push(&[0x00, 0x01, 0x00, 0x02, 0x00]);
+    assert_eq!(decoder.decode().unwrap(), Some(1));
+    assert_eq!(decoder.decode().unwrap(), Some(2));
+    assert_eq!(decoder.decode().unwrap(), None);
+    assert_eq!(decoder.buffered(), 1);
+    decoder.push(&[0x03]);
+    assert_eq!(decoder.decode().unwrap(), Some(3));
+}
+
+#[test]
+fn test_decode_as_borrowed() {
+    let mut decoder = Decoder::<String>::new();
+    decoder.push(&[3, b'f', b'o']);
+    assert_eq!(decoder.peek(), Some(3));
+    assert_eq!(decoder.decode_as::<&str>().unwrap(), None);
+    decoder.push(&[b'o', 1, b'x']);
+    assert_eq!(decoder.decode_as::<&str>().unwrap(), Some("foo"));
+    assert_eq!(decoder.buffered(), 2);
+    assert_eq!(decoder.decode().unwrap(), Some("x".to_string()));
+    assert_eq!(decoder.peek(), None);
+}
+
+#[test]
+fn test_invalid_value() {
+    let mut decoder = Decoder::<String>::new();
+    decoder.push(&[2, 0xff, b'a']);
+    assert!(matches!(
+        decoder.decode().unwrap_err().kind(),
+        Error::ExpectedAsciiCharacter
+    ));
+}
+
+#[test]
+fn test_config() {
+    let config = Config::new().with_all_primitives();
+    let mut decoder = Decoder::<(bool, i16)>::with_config(config);
+    decoder.push(&[1, 0xff]);
+    assert_eq!(decoder.decode().unwrap(), None);
+    decoder.push(&[0xfe]);
+    assert_eq!(decoder.decode().unwrap(), Some((true, -2)));
+}
@@ -0,0 +1,145 @@
+use std::fmt::{self, Display};
+use std::{error, io, result};
+
+use serde::{de, ser};
+
+/// The result of serializing or deserializing with this codec.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Everything that can go wrong while serializing or deserializing. Errors from deserializing
+/// come wrapped in `At`, so use `kind` to match on what went wrong.
+#[derive(Debug)]
+pub enum Error {
+    /// An error raised by a type's own `Serialize` or `Deserialize` impl, for example because a
+    /// struct is missing a field.
+    Message(String),
+
+    // The rest are raised by the format itself.
+    /// Unexpected end of input. Input that runs out early is reported as an `Io` error of kind
+    /// `UnexpectedEof` instead, since that's what the readers return.
+    Eof,
+    /// A string or char contained a byte that isn't ASCII.
+    ExpectedAsciiCharacter,
+    /// A char was encoded as a string that wasn't one character long.
+    ExpectedSingleLengthString,
+    /// A bool was encoded as a byte other than 0 or 1.
+    ExpectedBool(u8),
+    /// There were bytes left over after deserializing a value from a slice.
+    TrailingBytes,
+    /// The type isn't part of the format, or isn't enabled in the `Config`.
+    UnsupportedType,
+    /// An enum variant's index is too large to be its u8 tag.
+    VariantTagTooLarge(u32),
+    /// An enum's tag didn't match any of its variants.
+    UnknownVariantTag(u8),
+    /// A string was longer than its length prefix can hold, which is the maximum given.
+    StringTooLong(usize),
+    /// A byte array or sequence was longer than its length prefix can hold, which is the maximum
+    /// given.
+    ArrayTooLong(usize),
+    /// Reading or writing failed.
+    Io(io::Error),
+
+    /// Any of the above that occurred during deserialization, along with where in the input it
+    /// happened.
+    At(Box<Position>, Box<Error>),
+}
+
+/// Where in the input a deserialization error occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// The byte offset from the start of the value being deserialized.
+    pub offset: usize,
+    /// The tag of the outermost enum being deserialized, which for messages is the message ID.
+    pub id: Option<u8>,
+    /// The path to the field being deserialized, for example `IAmDispatcher.roads[3]`.
+    pub path: String,
+}
+
+impl Error {
+    /// The error without any position information.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::At(_, err) => err,
+            err => err,
+        }
+    }
+
+    /// Where in the input the error occurred, if it happened during deserialization.
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            Error::At(position, _) => Some(position),
+            _ => None,
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Message(msg) => formatter.write_str(msg),
+            Error::Io(err) => err.fmt(formatter),
+            Error::Eof => formatter.write_str("unexpected end of input"),
+            Error::ExpectedAsciiCharacter => formatter.write_str("expected an ASCII character"),
+            Error::ExpectedSingleLengthString => {
+                formatter.write_str("expected a single ASCII character")
+            }
+            Error::ExpectedBool(byte) => {
+                write!(formatter, "expected a bool of 0 or 1, got {byte:#04x}")
+            }
+            Error::TrailingBytes => formatter.write_str("trailing bytes"),
+            Error::UnsupportedType => formatter.write_str("unsupported type"),
+            Error::VariantTagTooLarge(index) => {
+                write!(
+                    formatter,
+                    "enum variant index {index} doesn't fit in a u8 tag"
+                )
+            }
+            Error::UnknownVariantTag(tag) => {
+                write!(formatter, "unrecognized variant tag: {tag:#04x}")
+            }
+            Error::StringTooLong(max) => {
+                write!(
+                    formatter,
+                    "the provided string exceeds the max of {max} bytes"
+                )
+            }
+            Error::ArrayTooLong(max) => {
+                write!(
+                    formatter,
+                    "the provided slice or array exceeds the max of {max} elements"
+                )
+            }
+            Error::At(position, err) => {
+                write!(formatter, "{err} at byte {}", position.offset)?;
+                if let Some(id) = position.id {
+                    write!(formatter, " of message {id:#04x}")?;
+                }
+                if !position.path.is_empty() {
+                    write!(formatter, " ({})", position.path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,61 @@
+//! A serde format for the binary protocols of the Protohackers problems, as used by the
+//! speed-daemon.
+//!
+//! Values are encoded field after field with nothing in between:
+//!
+//! - Unsigned integers are big-endian and fixed width.
+//! - Strings are ASCII, and along with byte arrays and sequences are preceded by their length.
+//!   Lengths take a single byte unless the [`Config`] says otherwise.
+//! - Structs and tuples are just their fields in order.
+//! - `Option`s are either their contents or nothing at all, so only work as the last field.
+//! - Enum variants are a u8 tag followed by their contents. The tag is the variant's index,
+//!   which serde's derive numbers from 0 in declaration order. An enum with tags of its own can
+//!   pass them as the index from a hand-written `Serialize` and `Deserialize`.
+//!
+//! Bools, signed integers, u64s and floats can be enabled with [`Config::with_all_primitives`].
+//! Maps and self-describing deserialization aren't supported.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! enum Message {
+//!     Error { msg: String },
+//!     Plate { plate: String, timestamp: u32 },
+//! }
+//!
+//! let msg = Message::Plate {
+//!     plate: "UN1X".to_string(),
+//!     timestamp: 1000,
+//! };
+//! let bytes = speed_daemon_codec::to_bytes(&msg).unwrap();
+//! assert_eq!(bytes, b"\x01\x04UN1X\x00\x00\x03\xe8");
+//! assert_eq!(speed_daemon_codec::from_bytes::<Message>(&bytes).unwrap(), msg);
+//! ```
+//!
+//! Values arriving over a stream can be decoded as their bytes come in with a [`Decoder`].
+
+#![warn(missing_docs)]
+
+mod config;
+mod de;
+mod decoder;
+mod error;
+mod read;
+mod ser;
+
+pub use config::{Config, PrefixWidth};
+pub use de::{
+    from_bytes, from_bytes_with_config, from_reader, from_reader_with_config, take_from_bytes,
+    take_from_bytes_with_config, Deserializer,
+};
+pub use decoder::Decoder;
+pub use error::{Error, Position, Result};
+pub use read::{Bytes, Input, IoReader, SliceReader};
+pub use ser::{to_bytes, to_bytes_with_config, to_writer, to_writer_with_config, Serializer};
+
+// Enum variants are encoded as a u8 tag followed by the variant's contents. The tag is the
+// variant's index, so it has to fit in a u8.
+fn variant_tag(variant_index: u32) -> Result<u8> {
+    u8::try_from(variant_index).map_err(|_| Error::VariantTagTooLarge(variant_index))
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Read};
+use std::ops::Deref;
+
+/// A source of bytes for the deserializer. Slices can lend out bytes that live as long as the
+/// input itself, which lets strings and byte arrays be borrowed instead of copied.
+pub trait Input<'de> {
+    /// Fill the buffer, failing with `UnexpectedEof` if there aren't enough bytes.
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Read the next `len` bytes, borrowing them from the input if possible.
+    fn read_bytes(&mut self, len: usize) -> io::Result<Bytes<'de>>;
+}
+
+/// Bytes read from an `Input`, either borrowed from it or copied out of it.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Bytes<'de> {
+    /// Bytes that live as long as the input.
+    Borrowed(&'de [u8]),
+    /// Bytes that had to be copied, since the input couldn't lend them out.
+    Copied(Vec<u8>),
+}
+
+impl Deref for Bytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Borrowed(bytes) => bytes,
+            Bytes::Copied(bytes) => bytes,
+        }
+    }
+}
+
+/// Input from any `io::Read`. Nothing can be borrowed, so every string is copied.
+pub struct IoReader<R> {
+    reader: R,
+}
+
+impl<R: Read> IoReader<R> {
+    /// Read input from the reader.
+    pub fn new(reader: R) -> Self {
+        IoReader { reader }
+    }
+}
+
+impl<R: Read> Input<'_> for IoReader<R> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.read_exact(buf)
+    }
+
+    // Lengths can come from untrusted input, so the buffer only grows as bytes actually arrive.
+    fn read_bytes(&mut self, len: usize) -> io::Result<Bytes<'static>> {
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        }
+        Ok(Bytes::Copied(bytes))
+    }
+}
+
+/// Input from a slice, which strings and byte arrays can borrow from.
+pub struct SliceReader<'de> {
+    slice: &'de [u8],
+}
+
+impl<'de> SliceReader<'de> {
+    /// Read input from the slice, starting at its first byte.
+    pub fn new(slice: &'de [u8]) -> Self {
+        SliceReader { slice }
+    }
+
+    /// The bytes that haven't been read yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.slice
+    }
+}
+
+impl<'de> Input<'de> for SliceReader<'de> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.slice.read_exact(buf)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<Bytes<'de>> {
+        if len > self.slice.len() {
+            self.slice = &[];
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        }
+        let (bytes, rest) = self.slice.split_at(len);
+        self.slice = rest;
+        Ok(Bytes::Borrowed(bytes))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_slice_reader_borrows() {
+    let input = [1, 2, 3, 4];
+    let mut reader = SliceReader::new(&input);
+    let bytes = reader.read_bytes(3).unwrap();
+    assert!(matches!(bytes, Bytes::Borrowed(&[1, 2, 3])));
+    assert_eq!(reader.remaining(), [4]);
+    let err = reader.read_bytes(2).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_io_reader_copies() {
+    let input = [1, 2, 3, 4];
+    let mut reader = IoReader::new(&input[..]);
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(reader.read_bytes(3).unwrap(), Bytes::Copied(vec![2, 3, 4]));
+}
@@ -1,31 +1,97 @@
 use serde::{ser, Serialize};
 use std::io::Write;
 
+use super::config::{Config, PrefixWidth};
 use super::error::{Error, Result};
+use super::variant_tag;
 
+/// Serializes values to a writer.
 pub struct Serializer<W: Write> {
     output: W,
+    config: Config,
 }
 
+impl<W: Write> Serializer<W> {
+    /// Serialize to the writer, using the default config.
+    pub fn new(output: W) -> Self {
+        Serializer {
+            output,
+            config: Config::default(),
+        }
+    }
+
+    /// Use a config other than the default.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Write a length prefix of the given width, failing with the error if it doesn't fit.
+    fn write_len(
+        &mut self,
+        len: usize,
+        width: PrefixWidth,
+        too_long: fn(usize) -> Error,
+    ) -> Result<()> {
+        if len > width.max() {
+            return Err(too_long(width.max()));
+        }
+        match width {
+            PrefixWidth::U8 => self.output.write_all(&[len as u8])?,
+            PrefixWidth::U16 => self.output.write_all(&(len as u16).to_be_bytes())?,
+            PrefixWidth::U32 => self.output.write_all(&(len as u32).to_be_bytes())?,
+        }
+        Ok(())
+    }
+
+    // Write a primitive that is only supported when the config says so.
+    fn write_primitive(&mut self, bytes: &[u8]) -> Result<()> {
+        if !self.config.all_primitives() {
+            return Err(Error::UnsupportedType);
+        }
+        self.output.write_all(bytes)?;
+        Ok(())
+    }
+}
+
+/// Serialize a value to a new buffer.
 pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    to_bytes_with_config(value, Config::default())
+}
+
+/// Serialize a value to a new buffer, using the config.
+pub fn to_bytes_with_config<T>(value: &T, config: Config) -> Result<Vec<u8>>
 where
     T: Serialize,
 {
     let mut writer = Vec::new();
-    to_writer(&mut writer, value)?;
+    to_writer_with_config(&mut writer, value, config)?;
     Ok(writer)
 }
 
+/// Serialize a value to a writer. The writer isn't buffered, so wrap it in a `BufWriter` if
+/// each write is expensive.
 pub fn to_writer<W: Write, T>(writer: W, value: &T) -> Result<()>
 where
     T: Serialize,
 {
-    let mut serializer = Serializer { output: writer };
+    to_writer_with_config(writer, value, Config::default())
+}
+
+/// Serialize a value to a writer, using the config.
+pub fn to_writer_with_config<W: Write, T>(writer: W, value: &T, config: Config) -> Result<()>
+where
+    T: Serialize,
+{
+    let mut serializer = Serializer::new(writer).with_config(config);
     value.serialize(&mut serializer)?;
     Ok(())
 }
 
-impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+impl<W: Write> ser::Serializer for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -37,51 +103,51 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
-    fn serialize_bool(self, _v: bool) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_primitive(&[v as u8])
     }
 
-    fn serialize_i8(self, _v: i8) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
-    fn serialize_i16(self, _v: i16) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
-    fn serialize_i32(self, _v: i32) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
-    fn serialize_i64(self, _v: i64) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        self.output.write(&[v])?;
+        self.output.write_all(&[v])?;
         Ok(())
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        self.output.write(&v.to_be_bytes())?;
+        self.output.write_all(&v.to_be_bytes())?;
         Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        self.output.write(&v.to_be_bytes())?;
+        self.output.write_all(&v.to_be_bytes())?;
         Ok(())
     }
 
-    fn serialize_u64(self, _v: u64) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
-    fn serialize_f32(self, _v: f32) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
-    fn serialize_f64(self, _v: f64) -> Result<()> {
-        Err(Error::UnsupportedType)
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_primitive(&v.to_be_bytes())
     }
 
     // Serialize a char as a single-character string.
@@ -91,23 +157,17 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 
     fn serialize_str(self, v: &str) -> Result<()> {
         let bytes = v.as_bytes();
-        if bytes.len() > 255 {
-            return Err(Error::StringTooLong);
-        }
         if !v.is_ascii() {
             return Err(Error::ExpectedAsciiCharacter);
         }
-        self.output.write(&[bytes.len() as u8])?;
-        self.output.write(bytes)?;
+        self.write_len(bytes.len(), self.config.str_prefix(), Error::StringTooLong)?;
+        self.output.write_all(bytes)?;
         Ok(())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        if v.len() > 255 {
-            return Err(Error::ArrayTooLong);
-        }
-        self.output.write(&[v.len() as u8])?;
-        self.output.write(v)?;
+        self.write_len(v.len(), self.config.bytes_prefix(), Error::ArrayTooLong)?;
+        self.output.write_all(v)?;
         Ok(())
     }
 
@@ -134,10 +194,10 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
     ) -> Result<()> {
-        Ok(())
+        self.serialize_u8(variant_tag(variant_index)?)
     }
 
     fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
@@ -150,25 +210,21 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        self.output.write_all(&[variant_tag(variant_index)?])?;
         value.serialize(&mut *self)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        match len {
-            Some(len) if len < 256 => {
-                self.output.write(&[len as u8])?;
-                Ok(self)
-            }
-            Some(_) => Err(Error::ArrayTooLong),
-            None => Err(Error::UnsupportedType),
-        }
+        let len = len.ok_or(Error::UnsupportedType)?;
+        self.write_len(len, self.config.seq_prefix(), Error::ArrayTooLong)?;
+        Ok(self)
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
@@ -186,11 +242,11 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
-        variant: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        variant.serialize(&mut *self)?;
+        self.output.write_all(&[variant_tag(variant_index)?])?;
         Ok(self)
     }
 
@@ -205,15 +261,16 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     fn serialize_struct_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
+        self.output.write_all(&[variant_tag(variant_index)?])?;
         Ok(self)
     }
 }
 
-impl<'a, W: Write> ser::SerializeSeq for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeSeq for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -231,7 +288,7 @@ impl<'a, W: Write> ser::SerializeSeq for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTuple for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -247,7 +304,7 @@ impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTupleStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -263,7 +320,7 @@ impl<'a, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTupleVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -279,7 +336,7 @@ impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeMap for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -302,7 +359,7 @@ impl<'a, W: Write> ser::SerializeMap for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeStruct for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -318,7 +375,7 @@ impl<'a, W: Write> ser::SerializeStruct for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeStructVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
@@ -399,6 +456,43 @@ fn test_tuple() {
     assert_eq!(to_bytes(&test).unwrap(), expected);
 }
 
+#[test]
+fn test_enum() {
+    #[derive(Serialize)]
+    enum Test {
+        Unit,
+        Newtype(u16),
+        Tuple(u8, u8),
+        Struct { a: u8 },
+    }
+    let test = (
+        Test::Unit,
+        Test::Newtype(1),
+        Test::Tuple(2, 3),
+        Test::Struct { a: 4 },
+    );
+    let expected = [0, 1, 0x00, 0x01, 2, 2, 3, 3, 4];
+    assert_eq!(to_bytes(&test).unwrap(), expected);
+}
+
+#[test]
+fn test_enum_tag_too_large() {
+    struct Test;
+
+    impl Serialize for Test {
+        fn serialize<S: ser::Serializer>(
+            &self,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            serializer.serialize_unit_variant("Test", 256, "Unit")
+        }
+    }
+    assert!(matches!(
+        to_bytes(&Test),
+        Err(Error::VariantTagTooLarge(256))
+    ));
+}
+
 #[test]
 fn test_option() {
     let test = Some("hello");
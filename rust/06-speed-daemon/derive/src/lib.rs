@@ -0,0 +1,368 @@
+//! Derives for speed-daemon's messages, so adding a message type is a matter of annotating its
+//! struct and listing it in the enum for its direction.
+//!
+//! ```ignore
+//! #[derive(Message, serde::Deserialize, serde::Serialize)]
+//! #[message(id = 0x20, sent_by(camera))]
+//! pub struct Plate {
+//!     pub plate: String,
+//!     pub timestamp: u32,
+//! }
+//!
+//! // Views that borrow from the input share the ID and senders of the message they borrow from.
+//! #[derive(Message, serde::Deserialize, serde::Serialize)]
+//! #[message(like = Plate)]
+//! pub struct PlateRef<'a> { ... }
+//!
+//! #[derive(MessageEnum)]
+//! pub enum IncomingMessage {
+//!     Plate(Plate),
+//!     ...
+//! }
+//!
+//! // Just the messages a camera may send.
+//! #[derive(MessageEnum)]
+//! #[message(role = camera, subset_of = IncomingMessage)]
+//! pub enum CameraIncoming {
+//!     Plate(Plate),
+//!     ...
+//! }
+//! ```
+//!
+//! The generated code refers to the `speed_daemon` crate by name.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Expr, Fields, GenericParam, Ident, Lifetime,
+    LifetimeParam, Path,
+};
+
+/// Implements `Message`, `SerializeMessage` and `DeserializeMessage` from a `#[message(...)]`
+/// attribute, which takes either an `id` and the roles it is `sent_by`, or a message
+/// to be `like`.
+#[proc_macro_derive(Message, attributes(message))]
+pub fn derive_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match message(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Implements `Serialize` and `Deserialize` for an enum with a single-field variant for each
+/// message, tagging each variant with its message's ID. Also adds methods for getting at the ID,
+/// name and senders of whichever message is held.
+///
+/// An enum holding some of another's messages can say so with `#[message(subset_of = ...)]`,
+/// which adds conversions to and from the other enum, matching variants by name. Adding a `role`
+/// as well implements `RoleMessage`, and checks at compile time that the role may send every
+/// message in the enum.
+#[proc_macro_derive(MessageEnum, attributes(message))]
+pub fn derive_message_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match message_enum(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn message(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut id: Option<Expr> = None;
+    let mut like: Option<Path> = None;
+    let mut sent_by = Vec::new();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("message")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("like") {
+                like = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("sent_by") {
+                meta.parse_nested_meta(|role| {
+                    let ident = role.path.require_ident()?;
+                    sent_by.push(role_variant(ident)?);
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("expected `id`, `like` or `sent_by`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let (id, sent_by) = match (id, like) {
+        (Some(id), None) if !sent_by.is_empty() => (quote!(#id), quote!(&[#(#sent_by),*])),
+        (None, Some(like)) if sent_by.is_empty() => (
+            quote!(<#like as ::speed_daemon::msg::Message>::ID),
+            quote!(<#like as ::speed_daemon::msg::Message>::SENT_BY),
+        ),
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "expected #[message(id = ..., sent_by(...))] or #[message(like = ...)]",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    // Messages holding borrowed data can only be deserialized from input that outlives them.
+    let mut de_generics = input.generics.clone();
+    let mut de = LifetimeParam::new(Lifetime::new("'de", Span::call_site()));
+    de.bounds
+        .extend(input.generics.lifetimes().map(|l| l.lifetime.clone()));
+    de_generics.params.insert(0, GenericParam::Lifetime(de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::speed_daemon::msg::Message for #name #ty_generics #where_clause {
+            const ID: u8 = #id;
+            const SENT_BY: &'static [::speed_daemon::msg::Role] = #sent_by;
+        }
+
+        impl #impl_generics ::speed_daemon::msg::SerializeMessage for #name #ty_generics
+            #where_clause {}
+
+        impl #de_impl_generics ::speed_daemon::msg::DeserializeMessage<'de>
+            for #name #ty_generics #where_clause {}
+    })
+}
+
+fn role_variant(ident: &Ident) -> syn::Result<proc_macro2::TokenStream> {
+    let variant = match ident.to_string().as_str() {
+        "unidentified" => quote!(Unidentified),
+        "camera" => quote!(Camera),
+        "dispatcher" => quote!(Dispatcher),
+        "server" => quote!(Server),
+        _ => {
+            return Err(syn::Error::new(
+                ident.span(),
+                "expected `unidentified`, `camera`, `dispatcher` or `server`",
+            ))
+        }
+    };
+    Ok(quote!(::speed_daemon::msg::Role::#variant))
+}
+
+fn message_enum(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "MessageEnum can only be derived for enums",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "MessageEnum can't be derived for generic enums",
+        ));
+    }
+
+    let mut role = None;
+    let mut subset_of: Option<Path> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("message")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("role") {
+                let ident = meta.value()?.parse::<Ident>()?;
+                role = Some(role_variant(&ident)?);
+            } else if meta.path.is_ident("subset_of") {
+                subset_of = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `role` or `subset_of`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut variants = Vec::new();
+    let mut types = Vec::new();
+    for variant in &data.variants {
+        match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                variants.push(&variant.ident);
+                types.push(&fields.unnamed[0].ty);
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "each variant must hold a single message",
+                ))
+            }
+        }
+    }
+
+    let name = &input.ident;
+    let enum_name = name.to_string();
+    let variant_names = variants.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    let indices = 0..variants.len();
+    let message = quote!(::speed_daemon::msg::Message);
+
+    let subset = match &subset_of {
+        Some(superset) => quote! {
+            impl ::std::convert::From<#name> for #superset {
+                fn from(msg: #name) -> Self {
+                    match msg {
+                        #(#name::#variants(msg) => #superset::#variants(msg),)*
+                    }
+                }
+            }
+
+            impl ::std::convert::TryFrom<#superset> for #name {
+                type Error = #superset;
+
+                fn try_from(msg: #superset) -> ::std::result::Result<Self, #superset> {
+                    match msg {
+                        #(#superset::#variants(msg) => Ok(#name::#variants(msg)),)*
+                        #[allow(unreachable_patterns)]
+                        msg => Err(msg),
+                    }
+                }
+            }
+        },
+        None => quote!(),
+    };
+    let role = match (role, &subset_of) {
+        (Some(role), Some(_)) => quote! {
+            impl ::speed_daemon::msg::RoleMessage for #name {
+                const ROLE: ::speed_daemon::msg::Role = #role;
+            }
+
+            #(const _: () = assert!(
+                ::speed_daemon::msg::sends(<#types as #message>::SENT_BY, #role),
+                concat!(stringify!(#variants), " isn't sent by this role"),
+            );)*
+        },
+        (Some(_), None) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "a role's messages must be a `subset_of` the incoming messages",
+            ))
+        }
+        (None, _) => quote!(),
+    };
+
+    Ok(quote! {
+        #subset
+        #role
+
+        impl #name {
+            /// The ID of the message held.
+            pub fn id(&self) -> u8 {
+                match self {
+                    #(Self::#variants(_) => <#types as #message>::ID,)*
+                }
+            }
+
+            /// The name of the message held.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    #(Self::#variants(_) => #variant_names,)*
+                }
+            }
+
+            /// The roles that may send the message held.
+            pub fn sent_by(&self) -> &'static [::speed_daemon::msg::Role] {
+                match self {
+                    #(Self::#variants(_) => <#types as #message>::SENT_BY,)*
+                }
+            }
+
+            /// Whether the message held may be sent by the role.
+            pub fn allowed_from(&self, role: ::speed_daemon::msg::Role) -> bool {
+                self.sent_by().contains(&role)
+            }
+        }
+
+        // The codec uses variant indices as tags, so each variant is given its message's ID as its
+        // index rather than its position in the enum.
+        const _: () = {
+            const VARIANTS: &[&str] = &[#(#variant_names),*];
+
+            impl ::serde::Serialize for #name {
+                fn serialize<S: ::serde::Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> ::std::result::Result<S::Ok, S::Error> {
+                    match self {
+                        #(Self::#variants(msg) => serializer.serialize_newtype_variant(
+                            #enum_name,
+                            <#types as #message>::ID as u32,
+                            VARIANTS[#indices],
+                            msg,
+                        ),)*
+                    }
+                }
+            }
+
+            impl<'de> ::serde::Deserialize<'de> for #name {
+                fn deserialize<D: ::serde::Deserializer<'de>>(
+                    deserializer: D,
+                ) -> ::std::result::Result<Self, D::Error> {
+                    // The variant with the ID it was tagged with.
+                    #[allow(non_camel_case_types)]
+                    enum Variant {
+                        #(#variants,)*
+                    }
+
+                    impl<'de> ::serde::Deserialize<'de> for Variant {
+                        fn deserialize<D: ::serde::Deserializer<'de>>(
+                            deserializer: D,
+                        ) -> ::std::result::Result<Self, D::Error> {
+                            deserializer.deserialize_identifier(VariantVisitor)
+                        }
+                    }
+
+                    struct VariantVisitor;
+
+                    impl<'de> ::serde::de::Visitor<'de> for VariantVisitor {
+                        type Value = Variant;
+
+                        fn expecting(
+                            &self,
+                            f: &mut ::std::fmt::Formatter,
+                        ) -> ::std::fmt::Result {
+                            f.write_str(concat!("a message ID of ", #enum_name))
+                        }
+
+                        fn visit_u64<E: ::serde::de::Error>(
+                            self,
+                            id: u64,
+                        ) -> ::std::result::Result<Self::Value, E> {
+                            #(if id == <#types as #message>::ID as u64 {
+                                return Ok(Variant::#variants);
+                            })*
+                            Err(E::invalid_value(::serde::de::Unexpected::Unsigned(id), &self))
+                        }
+                    }
+
+                    struct Visitor;
+
+                    impl<'de> ::serde::de::Visitor<'de> for Visitor {
+                        type Value = #name;
+
+                        fn expecting(
+                            &self,
+                            f: &mut ::std::fmt::Formatter,
+                        ) -> ::std::fmt::Result {
+                            f.write_str(concat!("enum ", #enum_name))
+                        }
+
+                        fn visit_enum<A: ::serde::de::EnumAccess<'de>>(
+                            self,
+                            data: A,
+                        ) -> ::std::result::Result<Self::Value, A::Error> {
+                            use ::serde::de::VariantAccess;
+                            match data.variant()? {
+                                #((Variant::#variants, variant) => {
+                                    variant.newtype_variant().map(#name::#variants)
+                                })*
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize_enum(#enum_name, VARIANTS, Visitor)
+                }
+            }
+        };
+    })
+}
@@ -0,0 +1,139 @@
+//! A line-based admin protocol for looking inside a running server. Each command is answered with
+//! one line per item, followed by an empty line.
+
+use super::dispatch::Registry;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "commands: cameras, dispatchers, pending, tickets, help, quit";
+
+/// Answer a single command from the state of the registry.
+pub fn respond<S>(command: &str, registry: &Registry<S>) -> Vec<String> {
+    match command {
+        "cameras" => registry
+            .cameras()
+            .into_iter()
+            .map(|(id, camera)| {
+                format!(
+                    "camera {id}: road {} mile {} limit {}",
+                    camera.road, camera.mile, camera.limit
+                )
+            })
+            .collect(),
+        "dispatchers" => registry
+            .dispatchers()
+            .into_iter()
+            .map(|(id, roads)| {
+                let roads = roads.iter().map(u16::to_string).collect::<Vec<_>>();
+                format!("dispatcher {id}: roads {}", roads.join(","))
+            })
+            .collect(),
+        "pending" => registry
+            .pending_by_road()
+            .into_iter()
+            .map(|(road, count)| format!("road {road}: {count} pending"))
+            .collect(),
+        "tickets" => registry
+            .recent()
+            .map(|ticket| {
+                format!(
+                    "{} road {} mile {} at {} to mile {} at {} speed {}.{:02}",
+                    ticket.plate,
+                    ticket.road,
+                    ticket.mile1,
+                    ticket.timestamp1,
+                    ticket.mile2,
+                    ticket.timestamp2,
+                    ticket.speed / 100,
+                    ticket.speed % 100,
+                )
+            })
+            .collect(),
+        "help" => vec![HELP.to_string()],
+        _ => vec![format!("unknown command: {command}"), HELP.to_string()],
+    }
+}
+
+/// Answer commands until the connection is closed or the operator quits. Each command is passed
+/// to `respond`, which is expected to lock whatever it needs for as short as possible.
+pub fn serve<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    respond: impl Fn(&str) -> Vec<String>,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        let command = line.trim();
+        match command {
+            "" => continue,
+            "quit" => break,
+            _ => (),
+        }
+        for line in respond(command) {
+            writeln!(writer, "{line}")?;
+        }
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msg;
+    use std::sync::mpsc;
+
+    fn registry() -> Registry<mpsc::Sender<msg::Ticket>> {
+        let mut registry = Registry::new();
+        registry.add_camera(msg::IAmCamera {
+            road: 123,
+            mile: 8,
+            limit: 60,
+        });
+        registry.register(vec![1, 2], mpsc::channel().0);
+        registry.dispatch(msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 123,
+            mile1: 8,
+            timestamp1: 0,
+            mile2: 9,
+            timestamp2: 45,
+            speed: 8000,
+        });
+        registry
+    }
+
+    #[test]
+    fn test_respond() {
+        let registry = registry();
+        assert_eq!(
+            respond("cameras", &registry),
+            vec!["camera 0: road 123 mile 8 limit 60"]
+        );
+        assert_eq!(
+            respond("dispatchers", &registry),
+            vec!["dispatcher 1: roads 1,2"]
+        );
+        assert_eq!(respond("pending", &registry), vec!["road 123: 1 pending"]);
+        assert_eq!(
+            respond("tickets", &registry),
+            vec!["UN1X road 123 mile 8 at 0 to mile 9 at 45 speed 80.00"]
+        );
+        assert_eq!(respond("bogus", &registry)[0], "unknown command: bogus");
+    }
+
+    #[test]
+    fn test_serve() {
+        let registry = registry();
+        let input = "cameras\n\n  pending  \nquit\ncameras\n";
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output, |command| {
+            respond(command, &registry)
+        })
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "camera 0: road 123 mile 8 limit 60\n\nroad 123: 1 pending\n\n"
+        );
+    }
+}
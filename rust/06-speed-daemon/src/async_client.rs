@@ -0,0 +1,296 @@
+//! An async implementation of the `Client` state machine. Bytes are buffered by the decoder until
+//! a whole message has arrived, so a slow client never causes partially read messages to be lost,
+//! and heartbeats are sent from a timer rather than between reads.
+
+use super::{codec, is_io_error, msg, Camera, ClientKind, Common, Dispatcher};
+use msg::SerializeMessage;
+use std::error::Error;
+use std::{future, io, marker};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::time;
+
+#[derive(Debug)]
+pub struct Client<R, W, Kind = Common> {
+    kind: marker::PhantomData<Kind>,
+    reader: R,
+    decoder: codec::Decoder<msg::IncomingMessage>,
+    wbuf: BufWriter<W>,
+    // The outer Option is whether a heartbeat was requested, and the inner is whether it was for
+    // a non-zero interval.
+    heartbeat: Option<Option<time::Interval>>,
+}
+
+pub enum CameraOrDispatcher<R, W> {
+    Camera(Client<R, W, Camera>, msg::IAmCamera),
+    Dispatcher(Client<R, W, Dispatcher>, msg::IAmDispatcher),
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Client<R, W> {
+    pub fn new(r: R, w: W) -> Self {
+        Self {
+            kind: marker::PhantomData,
+            reader: r,
+            decoder: codec::Decoder::new(),
+            wbuf: BufWriter::new(w),
+            heartbeat: None,
+        }
+    }
+
+    pub async fn run_until_specialized(
+        mut self,
+    ) -> Result<CameraOrDispatcher<R, W>, Box<dyn Error>> {
+        loop {
+            match self.next_message().await {
+                Ok(msg::CommonIncoming::WantHeartbeat(want_heartbeat)) => {
+                    if let Err(err) = self.want_heartbeat(want_heartbeat) {
+                        return Err(self.reject(err).await);
+                    }
+                }
+                Ok(msg::CommonIncoming::IAmCamera(msg)) => {
+                    return Ok(CameraOrDispatcher::Camera(self.into_kind(), msg))
+                }
+                Ok(msg::CommonIncoming::IAmDispatcher(msg)) => {
+                    return Ok(CameraOrDispatcher::Dispatcher(self.into_kind(), msg))
+                }
+                Err(err) => return Err(self.reject(err).await),
+            }
+        }
+    }
+
+    fn into_kind<Kind>(self) -> Client<R, W, Kind> {
+        Client {
+            kind: marker::PhantomData,
+            reader: self.reader,
+            decoder: self.decoder,
+            wbuf: self.wbuf,
+            heartbeat: self.heartbeat,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Client<R, W, Camera> {
+    /// Wait for and handle a single message from the camera, returning any plate it observed.
+    /// This only waits on reading and the heartbeat timer, so it can be used in `select!`.
+    pub async fn run_once(&mut self) -> Result<Option<msg::Plate>, Box<dyn Error>> {
+        let result = match self.next_message().await {
+            Ok(msg::CameraIncoming::Plate(plate)) => Ok(Some(plate)),
+            Ok(msg::CameraIncoming::WantHeartbeat(want_heartbeat)) => {
+                self.want_heartbeat(want_heartbeat).map(|_| None)
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(plate) => Ok(plate),
+            Err(err) => Err(self.reject(err).await),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Client<R, W, Dispatcher> {
+    /// Send a ticket to the dispatcher.
+    pub async fn send_ticket(&mut self, ticket: &msg::Ticket) -> Result<(), Box<dyn Error>> {
+        self.send(ticket).await
+    }
+
+    /// Wait for and handle a single message from the dispatcher. This only waits on reading and
+    /// the heartbeat timer, so it can be used in `select!` alongside a source of tickets.
+    pub async fn run_once(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = match self.next_message().await {
+            Ok(msg::DispatcherIncoming::WantHeartbeat(want_heartbeat)) => {
+                self.want_heartbeat(want_heartbeat)
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => Err(self.reject(err).await),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin, Kind: ClientKind> Client<R, W, Kind> {
+    /// Wait for the next complete message, sending heartbeats in the meantime. Messages the client
+    /// isn't allowed to send in its role are rejected.
+    async fn next_message(&mut self) -> Result<Kind::Incoming, Box<dyn Error>> {
+        let mut chunk = [0; 1024];
+        loop {
+            if let Some(msg) = self.decoder.decode()? {
+                return msg.for_role();
+            }
+            tokio::select! {
+                n = self.reader.read(&mut chunk) => {
+                    match n? {
+                        0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                        n => self.decoder.push(&chunk[..n]),
+                    }
+                }
+                _ = tick(&mut self.heartbeat) => self.send(&msg::Heartbeat).await?,
+            }
+        }
+    }
+
+    fn want_heartbeat(&mut self, heartbeat: msg::WantHeartbeat) -> Result<(), Box<dyn Error>> {
+        if self.heartbeat.is_some() {
+            return Err("heartbeat already requested".into());
+        }
+        let period: std::time::Duration = heartbeat.interval.into();
+        self.heartbeat = Some((!period.is_zero()).then(|| {
+            let mut interval = time::interval(period);
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+            interval
+        }));
+        Ok(())
+    }
+
+    async fn send<T: SerializeMessage>(&mut self, msg: &T) -> Result<(), Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        msg.to_writer(&mut bytes)?;
+        self.wbuf.write_all(&bytes).await?;
+        self.wbuf.flush().await?;
+        Ok(())
+    }
+
+    /// Tell the client why it is about to be disconnected.
+    async fn reject(&mut self, err: Box<dyn Error>) -> Box<dyn Error> {
+        if !is_io_error(err.as_ref()) {
+            let _ = self.send(&msg::Error(err.to_string())).await;
+        }
+        err
+    }
+}
+
+/// Wait for the next heartbeat, or forever if none were requested.
+async fn tick(heartbeat: &mut Option<Option<time::Interval>>) {
+    match heartbeat {
+        Some(Some(interval)) => {
+            interval.tick().await;
+        }
+        _ => future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use msg::Message;
+
+    fn plate(plate: &str, timestamp: u32) -> msg::Plate {
+        msg::Plate {
+            plate: plate.to_string(),
+            timestamp,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_messages() {
+        let camera = msg::IAmCamera {
+            road: 123,
+            mile: 8,
+            limit: 60,
+        };
+        let input = codec::to_bytes(&(
+            (msg::IAmCamera::ID, camera.clone()),
+            (msg::Plate::ID, plate("UN1X", 1000)),
+        ))
+        .unwrap();
+
+        let (mut tx, rx) = tokio::io::duplex(64);
+        let client = Client::new(rx, tokio::io::sink());
+        let writer = tokio::spawn(async move {
+            // Trickle the bytes in one at a time.
+            for byte in input {
+                tx.write_all(&[byte]).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            tx
+        });
+
+        let mut client = match client.run_until_specialized().await.unwrap() {
+            CameraOrDispatcher::Camera(client, c) if c == camera => client,
+            _ => panic!("expected a camera"),
+        };
+        assert_eq!(client.run_once().await.unwrap(), Some(plate("UN1X", 1000)));
+        drop(writer.await.unwrap());
+        assert!(client.run_once().await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_without_traffic() {
+        let input = codec::to_bytes(&(msg::WantHeartbeat::ID, 10_u32)).unwrap();
+        let (mut client_side, server_side) = tokio::io::duplex(64);
+        let (reader, writer) = tokio::io::split(server_side);
+        let mut client = Client::new(reader, writer);
+        client_side.write_all(&input).await.unwrap();
+
+        // Request the heartbeat, then wait on an idle connection.
+        let msg = client.next_message().await.unwrap();
+        match msg {
+            msg::CommonIncoming::WantHeartbeat(want) => client.want_heartbeat(want).unwrap(),
+            _ => panic!("expected a heartbeat request"),
+        }
+        let idle = time::timeout(time::Duration::from_millis(2500), client.next_message());
+        assert!(idle.await.is_err());
+
+        let mut output = vec![0; 8];
+        let n = client_side.read(&mut output).await.unwrap();
+        // One immediately and one per second after.
+        assert_eq!(output[..n], [msg::Heartbeat::ID; 3]);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_zero_heartbeat() {
+        let input = codec::to_bytes(&(
+            (msg::WantHeartbeat::ID, 0_u32),
+            (msg::WantHeartbeat::ID, 0_u32),
+        ))
+        .unwrap();
+        let mut output = Vec::new();
+        let client = Client::new(&input[..], &mut output);
+        assert!(client.run_until_specialized().await.is_err());
+        let expected = codec::to_bytes(&(
+            msg::Error::ID,
+            msg::Error("heartbeat already requested".to_string()),
+        ))
+        .unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher() {
+        let dispatcher = msg::IAmDispatcher { roads: vec![1, 2] };
+        let ticket = msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 1,
+            mile1: 8,
+            timestamp1: 0,
+            mile2: 9,
+            timestamp2: 45,
+            speed: 8000,
+        };
+        let input = codec::to_bytes(&(
+            (msg::IAmDispatcher::ID, dispatcher.clone()),
+            (msg::Plate::ID, plate("UN1X", 1000)),
+        ))
+        .unwrap();
+
+        let mut output = Vec::new();
+        let client = Client::new(&input[..], &mut output);
+        let mut client = match client.run_until_specialized().await.unwrap() {
+            CameraOrDispatcher::Dispatcher(client, d) if d == dispatcher => client,
+            _ => panic!("expected a dispatcher"),
+        };
+        client.send_ticket(&ticket).await.unwrap();
+        assert!(client.run_once().await.is_err());
+        drop(client);
+
+        let expected = codec::to_bytes(&(
+            (msg::Ticket::ID, ticket),
+            (
+                msg::Error::ID,
+                msg::Error("plates can only be sent by cameras".to_string()),
+            ),
+        ))
+        .unwrap();
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,695 @@
+//! Simulates cameras and dispatchers against a running server. Every ticket received is checked
+//! against the observations that were sent, and every expected ticket has to arrive, with the
+//! time from the observation that caused each ticket to its delivery reported as latency.
+//!
+//! usage: loadgen [--addr HOST:PORT] [--roads M] [--cameras N] [--cars K] [--limit MPH]
+//!                [--speed fixed:MPH | uniform:MIN:MAX | normal:MEAN:STDDEV]
+//!                [--dispatchers D] [--seed SEED] [--prefix PLATE] [--timeout SECS] [--check]
+//!
+//! Plates are named after the prefix, which defaults to one based on the time, so repeated runs
+//! against the same server don't see each other's tickets.
+
+use speed_daemon::codec;
+use speed_daemon::msg::{self, SerializeMessage};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::{env, process, thread, time};
+
+/// Miles between neighbouring cameras on a road.
+const CAMERA_SPACING: u16 = 10;
+
+/// How long to keep listening for duplicate tickets once all the expected ones have arrived.
+const GRACE_PERIOD: time::Duration = time::Duration::from_millis(500);
+
+#[derive(Debug)]
+struct Options {
+    addr: String,
+    roads: u16,
+    cameras: u16,
+    cars: u32,
+    limit: u16,
+    speed: Speed,
+    dispatchers: u16,
+    seed: u64,
+    prefix: String,
+    timeout: time::Duration,
+    check: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:1337".to_string(),
+            roads: 4,
+            cameras: 12,
+            cars: 1000,
+            limit: 60,
+            speed: Speed::Uniform(40.0, 80.0),
+            dispatchers: 2,
+            seed: 0x5eed,
+            prefix: default_prefix(),
+            timeout: time::Duration::from_secs(10),
+            check: false,
+        }
+    }
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut options = Self::default();
+        while let Some(flag) = args.next() {
+            if flag == "--check" {
+                options.check = true;
+                continue;
+            }
+            let value = args.next().ok_or(format!("missing value for {flag}"))?;
+            match flag.as_str() {
+                "--addr" => options.addr = value,
+                "--roads" => options.roads = value.parse()?,
+                "--cameras" => options.cameras = value.parse()?,
+                "--cars" => options.cars = value.parse()?,
+                "--limit" => options.limit = value.parse()?,
+                "--speed" => options.speed = value.parse()?,
+                "--dispatchers" => options.dispatchers = value.parse()?,
+                "--seed" => options.seed = value.parse()?,
+                "--prefix" => options.prefix = value,
+                "--timeout" => options.timeout = time::Duration::from_secs(value.parse()?),
+                _ => return Err(format!("unknown flag: {flag}").into()),
+            }
+        }
+        if options.roads == 0 || options.cameras < 2 * options.roads {
+            return Err("every road needs at least two cameras".into());
+        }
+        // Miles have to fit in a u16.
+        let last_mile = (options.cameras / options.roads - 1) as u64 * CAMERA_SPACING as u64;
+        if last_mile > u16::MAX as u64 {
+            return Err("too many cameras per road".into());
+        }
+        // Each car starts on a day of its own, and timestamps have to fit in a u32 even for a
+        // car going 1 mph, the slowest a car goes.
+        if options.cars as u64 * 86400 + last_mile * 3600 > u32::MAX as u64 - 3600 {
+            return Err("too many cars".into());
+        }
+        if options.dispatchers == 0 || options.dispatchers > options.roads {
+            return Err("there must be between one dispatcher and one per road".into());
+        }
+        Ok(options)
+    }
+}
+
+fn default_prefix() -> String {
+    let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH);
+    format!("{:04X}", now.unwrap_or_default().as_secs() & 0xffff)
+}
+
+/// The distribution car speeds are drawn from, in miles per hour.
+#[derive(Debug)]
+enum Speed {
+    Fixed(f64),
+    Uniform(f64, f64),
+    Normal(f64, f64),
+}
+
+impl Speed {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        let speed = match *self {
+            Speed::Fixed(speed) => speed,
+            Speed::Uniform(min, max) => min + (max - min) * rng.next_f64(),
+            // Box-Muller transform.
+            Speed::Normal(mean, stddev) => {
+                let (u1, u2) = (1.0 - rng.next_f64(), rng.next_f64());
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                mean + stddev * z
+            }
+        };
+        speed.max(1.0)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        let number = |i: usize| -> Result<f64, Self::Err> {
+            Ok(parts.get(i).ok_or("missing speed parameter")?.parse()?)
+        };
+        match parts[0] {
+            "fixed" => Ok(Speed::Fixed(number(1)?)),
+            "uniform" => Ok(Speed::Uniform(number(1)?, number(2)?)),
+            "normal" => Ok(Speed::Normal(number(1)?, number(2)?)),
+            _ => Err(format!("unknown speed distribution: {s}").into()),
+        }
+    }
+}
+
+/// A small xorshift generator, so runs are reproducible from their seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// A simulated car and every camera it passed.
+#[derive(Debug)]
+struct Car {
+    road: u16,
+    // The mile and timestamp of each sighting, in the order they happened.
+    sightings: Vec<(u16, u32)>,
+}
+
+/// Everything the simulation sends, and what it expects back.
+struct Plan {
+    cameras: Vec<(msg::IAmCamera, Vec<msg::Plate>)>,
+    cars: HashMap<String, Car>,
+    expected: HashSet<String>,
+}
+
+impl Plan {
+    fn new(options: &Options) -> Self {
+        let mut rng = Rng(options.seed.max(1));
+        let per_road = options.cameras / options.roads;
+        let mut cameras = Vec::new();
+        for road in 0..options.roads {
+            for i in 0..per_road {
+                let camera = msg::IAmCamera {
+                    road,
+                    mile: i * CAMERA_SPACING,
+                    limit: options.limit,
+                };
+                cameras.push((camera, Vec::new()));
+            }
+        }
+
+        let mut cars = HashMap::new();
+        let mut expected = HashSet::new();
+        for i in 0..options.cars {
+            let plate = format!("{}{i:06}", options.prefix);
+            let road = (i % options.roads as u32) as u16;
+            let speed = options.speed.sample(&mut rng);
+            // Each car starts on a day of its own, though slow cars on long roads can be seen on
+            // the next day or later too.
+            let start = i * 86400 + (rng.next_u64() % 3600) as u32;
+            let mut car = Car {
+                road,
+                sightings: Vec::new(),
+            };
+            for (camera, plates) in &mut cameras {
+                if camera.road != road {
+                    continue;
+                }
+                let timestamp = start + (camera.mile as f64 * 3600.0 / speed).round() as u32;
+                car.sightings.push((camera.mile, timestamp));
+                plates.push(msg::Plate {
+                    plate: plate.clone(),
+                    timestamp,
+                });
+            }
+            if speeding(&car, options.limit) {
+                expected.insert(plate.clone());
+            }
+            cars.insert(plate, car);
+        }
+        Self {
+            cameras,
+            cars,
+            expected,
+        }
+    }
+}
+
+// Speeds are worked out here straight from the spec rather than with the server's own code, so
+// a bug there can't pass its own check.
+
+/// The average speed between two `(mile, timestamp)` sightings in hundredths of a mile per hour,
+/// rounded to the nearest hundredth. Returns None unless the second sighting came after the first.
+fn average_speed((mile1, timestamp1): (u16, u32), (mile2, timestamp2): (u16, u32)) -> Option<u16> {
+    if timestamp2 <= timestamp1 {
+        return None;
+    }
+    // Both operands are exact, so a speed exactly halfway between two hundredths stays that way
+    // and rounds up.
+    let speed = f64::from(mile1.abs_diff(mile2)) * 360000.0 / f64::from(timestamp2 - timestamp1);
+    Some(speed.round().min(f64::from(u16::MAX)) as u16)
+}
+
+/// Whether a speed in hundredths of a mile per hour is 0.5 mph or more over the limit.
+fn over_limit(speed: u16, limit: u16) -> bool {
+    f64::from(speed) >= (f64::from(limit) + 0.5) * 100.0
+}
+
+fn speeding(car: &Car, limit: u16) -> bool {
+    car.sightings.iter().enumerate().any(|(i, first)| {
+        car.sightings[i + 1..]
+            .iter()
+            .filter_map(|second| average_speed(*first, *second))
+            .any(|speed| over_limit(speed, limit))
+    })
+}
+
+/// Check a ticket against the sightings of the car it was issued to.
+fn verify(ticket: &msg::Ticket, cars: &HashMap<String, Car>, limit: u16) -> Result<(), String> {
+    let car = cars
+        .get(&ticket.plate)
+        .ok_or(format!("unknown plate {}", ticket.plate))?;
+    let first = (ticket.mile1, ticket.timestamp1);
+    let second = (ticket.mile2, ticket.timestamp2);
+    if ticket.road != car.road {
+        return Err(format!("{} ticketed on the wrong road", ticket.plate));
+    }
+    if !car.sightings.contains(&first) || !car.sightings.contains(&second) {
+        return Err(format!(
+            "{} ticketed for sightings never sent",
+            ticket.plate
+        ));
+    }
+    match average_speed(first, second) {
+        Some(speed) if speed != ticket.speed => Err(format!(
+            "{} ticketed at {} but went {speed}",
+            ticket.plate, ticket.speed
+        )),
+        Some(speed) if !over_limit(speed, limit) => {
+            Err(format!("{} ticketed under the limit", ticket.plate))
+        }
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "{} ticketed with sightings out of order",
+            ticket.plate
+        )),
+    }
+}
+
+/// Read the next message from the server, or None if the read timed out.
+fn read_message(
+    stream: &mut TcpStream,
+    decoder: &mut codec::Decoder<msg::OutgoingMessage>,
+) -> Result<Option<msg::OutgoingMessage>, Box<dyn Error>> {
+    let mut chunk = [0; 1024];
+    loop {
+        if let Some(msg) = decoder.decode()? {
+            return Ok(Some(msg));
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return Err("server closed the connection".into()),
+            Ok(n) => decoder.push(&chunk[..n]),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// When each plate's sightings were sent, for working out latency.
+type SentAt = Arc<Mutex<HashMap<(String, u32), time::Instant>>>;
+
+/// Connect a dispatcher for the roads and forward every ticket it receives, along with when it
+/// arrived.
+fn dispatcher(
+    addr: &str,
+    roads: Vec<u16>,
+    tickets: mpsc::Sender<(msg::Ticket, time::Instant)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut stream = TcpStream::connect(addr)?;
+    msg::IAmDispatcher { roads }.to_writer(&mut stream)?;
+    let mut decoder = codec::Decoder::new();
+    loop {
+        let ticket = match read_message(&mut stream, &mut decoder)? {
+            Some(msg::OutgoingMessage::Ticket(ticket)) => ticket,
+            Some(msg::OutgoingMessage::Error(err)) => return Err(err.0.into()),
+            _ => continue,
+        };
+        if tickets.send((ticket, time::Instant::now())).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Connect a camera and send its plates in the order they were seen, keeping the connection
+/// open until the run is over.
+fn camera(
+    addr: &str,
+    camera: msg::IAmCamera,
+    mut plates: Vec<msg::Plate>,
+    sent_at: SentAt,
+    done: mpsc::Receiver<()>,
+) -> Result<(), Box<dyn Error>> {
+    let stream = TcpStream::connect(addr)?;
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    camera.to_writer(&mut writer)?;
+    plates.sort_by_key(|plate| plate.timestamp);
+    for plate in plates {
+        let key = (plate.plate.clone(), plate.timestamp);
+        sent_at.lock().unwrap().insert(key, time::Instant::now());
+        plate.to_writer(&mut writer)?;
+        writer.flush()?;
+    }
+    let _ = done.recv();
+    Ok(())
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(2);
+        }
+    };
+    let ok = if options.check {
+        check(&options.addr)
+    } else {
+        run(&options)
+    };
+    if !ok {
+        process::exit(1);
+    }
+}
+
+/// Run the simulation, returning whether the server got everything right.
+fn run(options: &Options) -> bool {
+    let plan = Plan::new(options);
+    let sightings = plan.cameras.iter().map(|(_, p)| p.len()).sum::<usize>();
+    println!(
+        "{} cars past {} cameras on {} roads, {} expected tickets",
+        plan.cars.len(),
+        plan.cameras.len(),
+        options.roads,
+        plan.expected.len()
+    );
+
+    // Dispatchers go first, so tickets aren't held back waiting for one.
+    let (ticket_tx, tickets) = mpsc::channel();
+    for i in 0..options.dispatchers {
+        let roads = (0..options.roads)
+            .filter(|road| road % options.dispatchers == i)
+            .collect();
+        let (addr, tx) = (options.addr.clone(), ticket_tx.clone());
+        thread::spawn(move || {
+            if let Err(err) = dispatcher(&addr, roads, tx) {
+                eprintln!("dispatcher {i} failed: {err}");
+            }
+        });
+    }
+    drop(ticket_tx);
+    thread::sleep(time::Duration::from_millis(100));
+
+    let start = time::Instant::now();
+    let sent_at = SentAt::default();
+    let mut done = Vec::new();
+    let mut senders = Vec::new();
+    for (info, plates) in plan.cameras {
+        let (addr, sent_at) = (options.addr.clone(), sent_at.clone());
+        let (done_tx, done_rx) = mpsc::channel();
+        done.push(done_tx);
+        senders.push(thread::spawn(move || {
+            if let Err(err) = camera(&addr, info.clone(), plates, sent_at, done_rx) {
+                eprintln!("camera {info:?} failed: {err}");
+            }
+        }));
+    }
+
+    // The days each plate has been ticketed for, and the number of tickets.
+    let mut received = HashMap::<String, HashSet<u32>>::new();
+    let mut count = 0;
+    let mut errors = Vec::new();
+    let mut latencies = Vec::new();
+    let mut last = start;
+    let deadline = start + options.timeout;
+    loop {
+        let now = time::Instant::now();
+        let wait = match received.len() >= plan.expected.len() {
+            true => GRACE_PERIOD,
+            false => deadline.saturating_duration_since(now),
+        };
+        let Ok((ticket, at)) = tickets.recv_timeout(wait) else {
+            break;
+        };
+        if let Err(err) = verify(&ticket, &plan.cars, options.limit) {
+            errors.push(err);
+        }
+        let sent = sent_at.lock().unwrap();
+        let sent = [ticket.timestamp1, ticket.timestamp2]
+            .iter()
+            .filter_map(|timestamp| sent.get(&(ticket.plate.clone(), *timestamp)))
+            .max()
+            .copied();
+        if let Some(sent) = sent {
+            latencies.push(at.saturating_duration_since(sent));
+        }
+        last = last.max(at);
+        count += 1;
+        let days = received.entry(ticket.plate.clone()).or_default();
+        let covered = (ticket.timestamp1 / 86400)..=(ticket.timestamp2 / 86400);
+        if covered.clone().any(|day| days.contains(&day)) {
+            errors.push(format!("{} ticketed twice on the same day", ticket.plate));
+        }
+        days.extend(covered);
+    }
+    drop(done);
+    for sender in senders {
+        let _ = sender.join();
+    }
+
+    let elapsed = last.saturating_duration_since(start);
+    let missing = plan
+        .expected
+        .iter()
+        .filter(|plate| !received.contains_key(*plate))
+        .count();
+    let unexpected = received
+        .keys()
+        .filter(|plate| !plan.expected.contains(*plate))
+        .count();
+    println!(
+        "sent {sightings} plates, last ticket after {elapsed:.2?} ({:.0} plates/s)",
+        sightings as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "received {count} tickets: {missing} missing, {unexpected} unexpected, {} invalid",
+        errors.len()
+    );
+    for err in errors.iter().take(10) {
+        println!("  {err}");
+    }
+    if !latencies.is_empty() {
+        latencies.sort();
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        println!(
+            "latency: min {:.2?} p50 {:.2?} p99 {:.2?} max {:.2?}",
+            latencies[0],
+            percentile(50),
+            percentile(99),
+            latencies[latencies.len() - 1]
+        );
+    }
+    missing == 0 && unexpected == 0 && errors.is_empty()
+}
+
+/// A conformance check, given the address of the server.
+type Check = fn(&str) -> Result<(), Box<dyn Error>>;
+
+/// Check the server handles misbehaving clients and heartbeats the way the spec says.
+fn check(addr: &str) -> bool {
+    let checks: [(&str, Check); 4] = [
+        (
+            "plate from an unidentified client",
+            check_unidentified_plate,
+        ),
+        ("client identifying twice", check_identify_twice),
+        ("heartbeat requested twice", check_duplicate_heartbeat),
+        ("heartbeats on an idle connection", check_heartbeats),
+    ];
+    let mut ok = true;
+    for (name, check) in checks {
+        match check(addr) {
+            Ok(()) => println!("PASS {name}"),
+            Err(err) => {
+                println!("FAIL {name}: {err}");
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Connect and send some bytes, returning the connection ready for reading.
+fn connect(addr: &str, bytes: &[u8]) -> Result<TcpStream, Box<dyn Error>> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(time::Duration::from_secs(2)))?;
+    stream.write_all(bytes)?;
+    Ok(stream)
+}
+
+/// Expect the server to send an error and hang up.
+fn expect_error(mut stream: TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut decoder = codec::Decoder::new();
+    loop {
+        match read_message(&mut stream, &mut decoder)? {
+            Some(msg::OutgoingMessage::Error(_)) => break,
+            Some(msg::OutgoingMessage::Heartbeat(_)) => continue,
+            Some(msg) => return Err(format!("expected an error, got {msg:?}").into()),
+            None => return Err("timed out waiting for an error".into()),
+        }
+    }
+    match stream.read(&mut [0]) {
+        Ok(0) => Ok(()),
+        _ => Err("connection left open after the error".into()),
+    }
+}
+
+fn check_unidentified_plate(addr: &str) -> Result<(), Box<dyn Error>> {
+    let plate = msg::Plate {
+        plate: "UN1X".to_string(),
+        timestamp: 0,
+    };
+    let mut bytes = Vec::new();
+    plate.to_writer(&mut bytes)?;
+    expect_error(connect(addr, &bytes)?)
+}
+
+fn check_identify_twice(addr: &str) -> Result<(), Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    msg::IAmDispatcher { roads: vec![1] }.to_writer(&mut bytes)?;
+    msg::IAmDispatcher { roads: vec![2] }.to_writer(&mut bytes)?;
+    expect_error(connect(addr, &bytes)?)
+}
+
+fn check_duplicate_heartbeat(addr: &str) -> Result<(), Box<dyn Error>> {
+    let want = msg::WantHeartbeat {
+        interval: msg::Decisecond(0),
+    };
+    let mut bytes = Vec::new();
+    want.to_writer(&mut bytes)?;
+    want.to_writer(&mut bytes)?;
+    expect_error(connect(addr, &bytes)?)
+}
+
+fn check_heartbeats(addr: &str) -> Result<(), Box<dyn Error>> {
+    let want = msg::WantHeartbeat {
+        interval: msg::Decisecond(1),
+    };
+    let mut bytes = Vec::new();
+    want.to_writer(&mut bytes)?;
+    let mut stream = connect(addr, &bytes)?;
+    let mut decoder = codec::Decoder::new();
+    let start = time::Instant::now();
+    for _ in 0..5 {
+        match read_message(&mut stream, &mut decoder)? {
+            Some(msg::OutgoingMessage::Heartbeat(_)) => (),
+            Some(msg) => return Err(format!("expected a heartbeat, got {msg:?}").into()),
+            None => return Err("timed out waiting for a heartbeat".into()),
+        }
+    }
+    // Five heartbeats a decisecond apart, allowing for scheduling slop.
+    let elapsed = start.elapsed();
+    if elapsed > time::Duration::from_millis(1000) {
+        return Err(format!("five heartbeats took {elapsed:?}").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Options {
+        Options::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    fn ticket(mile1: u16, timestamp1: u32, mile2: u16, timestamp2: u32, speed: u16) -> msg::Ticket {
+        msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 1,
+            mile1,
+            timestamp1,
+            mile2,
+            timestamp2,
+            speed,
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        let options = parse(&["--roads", "2", "--speed", "normal:60:5", "--check"]);
+        assert_eq!(options.roads, 2);
+        assert!(matches!(options.speed, Speed::Normal(mean, _) if mean == 60.0));
+        assert!(options.check);
+
+        let try_parse = |args: &[&str]| Options::parse(args.iter().map(|arg| arg.to_string()));
+        assert!(try_parse(&["--roads"]).is_err());
+        assert!(try_parse(&["--cameras", "3", "--roads", "2"]).is_err());
+        assert!(try_parse(&["--cameras", "6554", "--roads", "1", "--dispatchers", "1"]).is_ok());
+        assert!(try_parse(&["--cameras", "6555", "--roads", "1", "--dispatchers", "1"]).is_err());
+        assert!(try_parse(&["--cars", "49000"]).is_ok());
+        assert!(try_parse(&[
+            "--cars",
+            "49000",
+            "--cameras",
+            "6000",
+            "--roads",
+            "1",
+            "--dispatchers",
+            "1"
+        ])
+        .is_err());
+        assert!(try_parse(&["--speed", "uniform:40"]).is_err());
+        assert!(try_parse(&["--bogus", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let options = parse(&["--cars", "100", "--speed", "fixed:70", "--prefix", "T"]);
+        let plan = Plan::new(&options);
+        assert_eq!(plan.cameras.len(), 12);
+        assert_eq!(plan.cars.len(), 100);
+        // Every car is speeding, and is seen by each camera on its road.
+        assert_eq!(plan.expected.len(), 100);
+        assert_eq!(plan.cars["T000042"].sightings.len(), 3);
+        let sightings = plan.cameras.iter().map(|(_, p)| p.len()).sum::<usize>();
+        assert_eq!(sightings, 300);
+
+        let options = parse(&["--cars", "100", "--speed", "fixed:60", "--prefix", "T"]);
+        assert!(Plan::new(&options).expected.is_empty());
+    }
+
+    #[test]
+    fn test_verify() {
+        let cars = HashMap::from([(
+            "UN1X".to_string(),
+            Car {
+                road: 1,
+                sightings: vec![(8, 0), (9, 45), (10, 120)],
+            },
+        )]);
+        assert_eq!(verify(&ticket(8, 0, 9, 45, 8000), &cars, 60), Ok(()));
+        assert!(verify(&ticket(8, 0, 9, 45, 7999), &cars, 60).is_err());
+        assert!(verify(&ticket(8, 0, 9, 46, 7826), &cars, 60).is_err());
+        assert!(verify(&ticket(9, 45, 10, 120, 4800), &cars, 60).is_err());
+        assert!(verify(&ticket(9, 45, 8, 0, 8000), &cars, 60).is_err());
+
+        let mut other_road = ticket(8, 0, 9, 45, 8000);
+        other_road.road = 2;
+        assert!(verify(&other_road, &cars, 60).is_err());
+        other_road.plate = "RE05BKG".to_string();
+        assert!(verify(&other_road, &cars, 60).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Replays a capture taken with SPEED_DAEMON_CAPTURE through a fresh ticket engine, and diffs the
+//! tickets it issues against the ones sent during the captured session.
+//!
+//! usage: replay CAPTURE
+//!
+//! The replay starts from empty state, so captures from a server restored from a store are only
+//! comparable if the store was empty too.
+
+use speed_daemon::capture::{self, Replay};
+use std::{env, process};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: replay CAPTURE");
+        process::exit(2);
+    };
+    let entries = match capture::read(&path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("could not read capture: {err}");
+            process::exit(2);
+        }
+    };
+    println!("replaying {} entries", entries.len());
+
+    let replay = Replay::new(entries);
+    let diff = replay.diff();
+    for ticket in &diff.recorded {
+        println!("- {ticket:?}");
+    }
+    for ticket in &diff.produced {
+        println!("+ {ticket:?}");
+    }
+    println!(
+        "{} tickets recorded, {} produced: {} only recorded (-), {} only produced (+)",
+        replay.recorded.len(),
+        replay.produced.len(),
+        diff.recorded.len(),
+        diff.produced.len()
+    );
+    if !diff.is_empty() {
+        process::exit(1);
+    }
+}
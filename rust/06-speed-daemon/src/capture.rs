@@ -0,0 +1,278 @@
+//! Capturing every message sent and received, so a session can be replayed later. Captures use
+//! the codec's binary format, with each message encoded exactly as it was on the wire.
+
+use super::ticket::{Ledger, TicketEngine};
+use super::{codec, msg};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time;
+
+pub type ConnectionId = u32;
+
+/// Which connection a message was on, and when.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Stamp {
+    pub connection: ConnectionId,
+    /// Milliseconds since the capture was started.
+    pub elapsed: u32,
+}
+
+/// A single captured message. Each variant's index is its tag in the codec, so new variants go at
+/// the end.
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Entry {
+    /// A message decoded from a client.
+    Incoming(Stamp, msg::IncomingMessage),
+    /// A message queued to be sent to a client. Tickets are only captured once they've been
+    /// written out, so a ticket re-routed after its dispatcher disconnected is captured once.
+    Outgoing(Stamp, msg::OutgoingMessage),
+    /// A plate passed to the ticket engine. Messages are captured as they are decoded, which
+    /// for different cameras isn't necessarily the order they are observed in, so observations
+    /// are captured separately.
+    Observed(Stamp, msg::IAmCamera, msg::Plate),
+}
+
+/// Appends every message passed to it to a file. Entries are written whole, so a capture cut off
+/// by a crash can still be read up to its last complete entry.
+#[derive(Debug)]
+pub struct Capture {
+    file: Mutex<File>,
+    start: time::Instant,
+    next_connection: AtomicU32,
+}
+
+impl Capture {
+    /// Start a new capture at the path, replacing anything already there.
+    pub fn create(path: impl AsRef<Path>) -> Result<Arc<Self>, Box<dyn Error>> {
+        Ok(Arc::new(Self {
+            file: Mutex::new(File::create(path)?),
+            start: time::Instant::now(),
+            next_connection: AtomicU32::new(0),
+        }))
+    }
+
+    /// A handle for capturing the messages on a new connection.
+    pub fn tap(self: &Arc<Self>) -> Tap {
+        Tap {
+            capture: self.clone(),
+            connection: self.next_connection.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn stamp(&self, connection: ConnectionId) -> Stamp {
+        let elapsed = self.start.elapsed().as_millis();
+        Stamp {
+            connection,
+            elapsed: elapsed.min(u32::MAX as u128) as u32,
+        }
+    }
+
+    // The session carries on without its capture if this fails, rather than being disconnected
+    // over it.
+    fn append(&self, entry: &Entry) {
+        let result = codec::to_bytes(entry)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|bytes| Ok(self.file.lock().unwrap().write_all(&bytes)?));
+        if let Err(err) = result {
+            println!("could not capture message: {err}");
+        }
+    }
+}
+
+/// Captures the messages on one connection.
+#[derive(Clone, Debug)]
+pub struct Tap {
+    capture: Arc<Capture>,
+    connection: ConnectionId,
+}
+
+impl Tap {
+    pub fn incoming(&self, msg: &msg::IncomingMessage) {
+        let stamp = self.capture.stamp(self.connection);
+        self.capture.append(&Entry::Incoming(stamp, msg.clone()));
+    }
+
+    pub fn outgoing(&self, msg: &msg::OutgoingMessage) {
+        let stamp = self.capture.stamp(self.connection);
+        self.capture.append(&Entry::Outgoing(stamp, msg.clone()));
+    }
+
+    /// Capture a plate being passed to the ticket engine. This must be called under the same lock
+    /// as the engine, so observations are captured in exactly the order they're made.
+    pub fn observed(&self, camera: &msg::IAmCamera, plate: msg::PlateRef) {
+        let stamp = self.capture.stamp(self.connection);
+        self.capture
+            .append(&Entry::Observed(stamp, camera.clone(), plate.into()));
+    }
+}
+
+/// Read every complete entry in a capture, oldest first.
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut decoder = codec::Decoder::new();
+    decoder.push(&fs::read(path)?);
+    let mut entries = Vec::new();
+    while let Some(entry) = decoder.decode()? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// The tickets sent during a captured session, alongside the ones a fresh ticket engine issues
+/// when fed the same observations in the same order.
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub recorded: Vec<msg::Ticket>,
+    pub produced: Vec<msg::Ticket>,
+}
+
+impl Replay {
+    pub fn new(entries: impl IntoIterator<Item = Entry>) -> Self {
+        let mut replay = Self::default();
+        let mut engine = TicketEngine::new();
+        let mut ledger = Ledger::new();
+        for entry in entries {
+            match entry {
+                Entry::Observed(_, camera, plate) => {
+                    let tickets = engine.observe(&camera, (&plate).into());
+                    let issued = tickets.into_iter().filter_map(|t| ledger.issue(t));
+                    replay.produced.extend(issued);
+                }
+                Entry::Outgoing(_, msg::OutgoingMessage::Ticket(ticket)) => {
+                    replay.recorded.push(ticket);
+                }
+                _ => (),
+            }
+        }
+        replay
+    }
+
+    /// Compare the recorded tickets with the ones the replay produced.
+    pub fn diff(&self) -> Diff<'_> {
+        let mut produced = self.produced.iter().collect::<Vec<_>>();
+        let mut recorded = Vec::new();
+        for ticket in &self.recorded {
+            match produced.iter().position(|t| *t == ticket) {
+                Some(i) => {
+                    produced.remove(i);
+                }
+                None => recorded.push(ticket),
+            }
+        }
+        Diff { recorded, produced }
+    }
+}
+
+/// The differences between a session's tickets and its replay's. Tickets still waiting for a
+/// dispatcher when the capture ended were never sent, so they show up as only produced. The
+/// order tickets were sent in isn't compared, since it depends on when dispatchers connected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diff<'a> {
+    /// Tickets that were sent, but not produced by the replay.
+    pub recorded: Vec<&'a msg::Ticket>,
+    /// Tickets produced by the replay, but never sent.
+    pub produced: Vec<&'a msg::Ticket>,
+}
+
+impl Diff<'_> {
+    /// Whether the replay produced exactly the recorded tickets.
+    pub fn is_empty(&self) -> bool {
+        self.recorded.is_empty() && self.produced.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{camera, plate, temp_path, ticket};
+
+    fn stamp(connection: ConnectionId) -> Stamp {
+        Stamp {
+            connection,
+            elapsed: 0,
+        }
+    }
+
+    #[test]
+    fn test_entry_encoding() {
+        let plate = msg::IncomingMessage::Plate(plate(45));
+        let entry = Entry::Incoming(stamp(7), plate.clone());
+        let bytes = codec::to_bytes(&entry).unwrap();
+        // The message follows the stamp exactly as it was on the wire.
+        assert_eq!(bytes[..9], [0, 0, 0, 0, 7, 0, 0, 0, 0]);
+        assert_eq!(bytes[9..], codec::to_bytes(&plate).unwrap());
+        assert_eq!(codec::from_bytes::<Entry>(&bytes).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_capture() {
+        let path = temp_path("capture");
+        let capture = Capture::create(&path).unwrap();
+        let (first, second) = (capture.tap(), capture.tap());
+        let identify = msg::IncomingMessage::IAmCamera(camera(0));
+        first.incoming(&identify);
+        second.outgoing(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat));
+        first.observed(&camera(0), (&plate(45)).into());
+
+        let entries = read(&path).unwrap();
+        assert!(
+            matches!(&entries[0], Entry::Incoming(s, m) if s.connection == 0 && *m == identify)
+        );
+        assert!(matches!(&entries[1], Entry::Outgoing(s, _) if s.connection == 1));
+        assert!(
+            matches!(&entries[2], Entry::Observed(s, c, p) if s.connection == 0 && *c == camera(0) && *p == plate(45))
+        );
+        assert_eq!(entries.len(), 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay() {
+        let entries = vec![
+            Entry::Incoming(stamp(0), msg::IncomingMessage::IAmCamera(camera(0))),
+            Entry::Incoming(stamp(1), msg::IncomingMessage::IAmCamera(camera(100))),
+            // Decoded in one order, but observed in the other.
+            Entry::Incoming(stamp(1), msg::IncomingMessage::Plate(plate(3600))),
+            Entry::Incoming(stamp(0), msg::IncomingMessage::Plate(plate(0))),
+            Entry::Observed(stamp(0), camera(0), plate(0)),
+            Entry::Observed(stamp(1), camera(100), plate(3600)),
+            Entry::Outgoing(
+                stamp(3),
+                msg::OutgoingMessage::Ticket(ticket(0, 3600, 10000)),
+            ),
+        ];
+        let replay = Replay::new(entries);
+        assert_eq!(replay.produced, vec![ticket(0, 3600, 10000)]);
+        assert!(replay.diff().is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let replay = Replay {
+            recorded: vec![
+                ticket(0, 3600, 10000),
+                ticket(0, 3600, 10000),
+                ticket(180000, 183600, 10000),
+            ],
+            produced: vec![
+                ticket(0, 3600, 10000),
+                ticket(176400, 180000, 10000),
+                ticket(86400, 90000, 10000),
+            ],
+        };
+        let diff = replay.diff();
+        // Tickets for the same car on the same day still have to be for the same observations.
+        assert_eq!(
+            diff.recorded,
+            vec![&ticket(0, 3600, 10000), &ticket(180000, 183600, 10000)]
+        );
+        assert_eq!(
+            diff.produced,
+            vec![&ticket(176400, 180000, 10000), &ticket(86400, 90000, 10000)]
+        );
+        assert!(!diff.is_empty());
+    }
+}
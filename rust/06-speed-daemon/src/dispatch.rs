@@ -0,0 +1,377 @@
+use super::{msg, ClientWriter};
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc;
+
+pub type DispatcherId = usize;
+pub type CameraId = usize;
+
+/// How many of the most recently dispatched tickets are kept around for inspection.
+const RECENT_TICKETS: usize = 32;
+
+/// A destination for tickets, typically a connected dispatcher.
+pub trait TicketSink {
+    /// Hand off a ticket, returning it back if it could not be delivered.
+    fn deliver(&self, ticket: msg::Ticket) -> Result<(), msg::Ticket>;
+}
+
+impl TicketSink for mpsc::Sender<msg::Ticket> {
+    fn deliver(&self, ticket: msg::Ticket) -> Result<(), msg::Ticket> {
+        self.send(ticket).map_err(|err| err.0)
+    }
+}
+
+/// Tickets are only queued for the client's session to write out, so this never blocks on the
+/// network while the registry is locked. Any the client doesn't receive are handed back by
+/// `Client::into_undelivered`.
+impl TicketSink for ClientWriter {
+    fn deliver(&self, ticket: msg::Ticket) -> Result<(), msg::Ticket> {
+        self.send_ticket(&ticket).map_err(|_| ticket)
+    }
+}
+
+/// Routes tickets to the dispatchers responsible for their road. Tickets for roads without a
+/// dispatcher are held until one connects. Connected cameras are tracked too, so the registry
+/// knows about every identified client.
+#[derive(Debug)]
+pub struct Registry<S> {
+    next_id: usize,
+    cameras: HashMap<CameraId, msg::IAmCamera>,
+    dispatchers: HashMap<DispatcherId, (Vec<u16>, S)>,
+    // Dispatchers responsible for each road, in the order they connected.
+    roads: HashMap<u16, Vec<DispatcherId>>,
+    pending: HashMap<u16, VecDeque<msg::Ticket>>,
+    recent: VecDeque<msg::Ticket>,
+}
+
+impl<S> Default for Registry<S> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            cameras: HashMap::new(),
+            dispatchers: HashMap::new(),
+            roads: HashMap::new(),
+            pending: HashMap::new(),
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl<S> Registry<S> {
+    /// Keep track of a connected camera until it is removed.
+    pub fn add_camera(&mut self, camera: msg::IAmCamera) -> CameraId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.cameras.insert(id, camera);
+        id
+    }
+
+    pub fn remove_camera(&mut self, id: CameraId) {
+        self.cameras.remove(&id);
+    }
+
+    /// The connected cameras, in the order they connected.
+    pub fn cameras(&self) -> Vec<(CameraId, &msg::IAmCamera)> {
+        let mut cameras = self
+            .cameras
+            .iter()
+            .map(|(id, c)| (*id, c))
+            .collect::<Vec<_>>();
+        cameras.sort_by_key(|(id, _)| *id);
+        cameras
+    }
+
+    /// The connected dispatchers and their roads, in the order they connected.
+    pub fn dispatchers(&self) -> Vec<(DispatcherId, &[u16])> {
+        let mut dispatchers = self
+            .dispatchers
+            .iter()
+            .map(|(id, (roads, _))| (*id, &roads[..]))
+            .collect::<Vec<_>>();
+        dispatchers.sort_by_key(|(id, _)| *id);
+        dispatchers
+    }
+
+    /// The number of tickets waiting for a dispatcher on each road that has any, by road.
+    pub fn pending_by_road(&self) -> Vec<(u16, usize)> {
+        let mut pending = self
+            .pending
+            .iter()
+            .filter(|(_, tickets)| !tickets.is_empty())
+            .map(|(road, tickets)| (*road, tickets.len()))
+            .collect::<Vec<_>>();
+        pending.sort();
+        pending
+    }
+
+    /// The most recently dispatched tickets, oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = &msg::Ticket> {
+        self.recent.iter()
+    }
+}
+
+impl<S: TicketSink> Registry<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a dispatcher for the given roads and deliver any tickets that were waiting for it.
+    pub fn register(&mut self, roads: Vec<u16>, sink: S) -> DispatcherId {
+        let id = self.next_id;
+        self.next_id += 1;
+        for road in &roads {
+            self.roads.entry(*road).or_default().push(id);
+        }
+        let pending = roads
+            .iter()
+            .filter_map(|road| self.pending.remove(road))
+            .flatten()
+            .collect::<Vec<_>>();
+        self.dispatchers.insert(id, (roads, sink));
+        for ticket in pending {
+            self.route(ticket);
+        }
+        id
+    }
+
+    /// Remove a dispatcher. Any tickets it was handed but did not deliver are routed to another
+    /// dispatcher or queued.
+    pub fn remove(&mut self, id: DispatcherId, undelivered: impl IntoIterator<Item = msg::Ticket>) {
+        if let Some((roads, _)) = self.dispatchers.remove(&id) {
+            for road in roads {
+                let ids = self.roads.entry(road).or_default();
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.roads.remove(&road);
+                }
+            }
+        }
+        for ticket in undelivered {
+            self.route(ticket);
+        }
+    }
+
+    /// Deliver a ticket to exactly one dispatcher for its road, or queue it if there are none.
+    pub fn dispatch(&mut self, ticket: msg::Ticket) {
+        if self.recent.len() == RECENT_TICKETS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ticket.clone());
+        self.route(ticket);
+    }
+
+    fn route(&mut self, mut ticket: msg::Ticket) {
+        for id in self.roads.get(&ticket.road).into_iter().flatten() {
+            let (_, sink) = &self.dispatchers[id];
+            match sink.deliver(ticket) {
+                Ok(()) => return,
+                Err(returned) => ticket = returned,
+            }
+        }
+        self.pending
+            .entry(ticket.road)
+            .or_default()
+            .push_back(ticket);
+    }
+
+    /// The number of tickets waiting for a dispatcher on the road.
+    pub fn pending(&self, road: u16) -> usize {
+        self.pending.get(&road).map_or(0, VecDeque::len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ticket(plate: &str, road: u16) -> msg::Ticket {
+        msg::Ticket {
+            plate: plate.to_string(),
+            road,
+            mile1: 0,
+            timestamp1: 0,
+            mile2: 100,
+            timestamp2: 3600,
+            speed: 10000,
+        }
+    }
+
+    #[test]
+    fn test_dispatch_by_road() {
+        let mut registry = Registry::new();
+        let (tx1, rx1) = mpsc::channel();
+        let (tx2, rx2) = mpsc::channel();
+        registry.register(vec![1, 2], tx1);
+        registry.register(vec![3], tx2);
+
+        registry.dispatch(ticket("A", 2));
+        registry.dispatch(ticket("B", 3));
+        assert_eq!(rx1.try_iter().collect::<Vec<_>>(), vec![ticket("A", 2)]);
+        assert_eq!(rx2.try_iter().collect::<Vec<_>>(), vec![ticket("B", 3)]);
+    }
+
+    #[test]
+    fn test_exactly_one_dispatcher() {
+        let mut registry = Registry::new();
+        let (tx1, rx1) = mpsc::channel();
+        let (tx2, rx2) = mpsc::channel();
+        registry.register(vec![1], tx1);
+        registry.register(vec![1], tx2);
+
+        registry.dispatch(ticket("A", 1));
+        assert_eq!(rx1.try_iter().count() + rx2.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_pending_until_connected() {
+        let mut registry = Registry::new();
+        registry.dispatch(ticket("A", 1));
+        registry.dispatch(ticket("B", 1));
+        registry.dispatch(ticket("C", 2));
+        assert_eq!(registry.pending(1), 2);
+        assert_eq!(registry.pending(2), 1);
+
+        let (tx, rx) = mpsc::channel();
+        registry.register(vec![1], tx);
+        assert_eq!(registry.pending(1), 0);
+        assert_eq!(registry.pending(2), 1);
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![ticket("A", 1), ticket("B", 1)]
+        );
+    }
+
+    #[test]
+    fn test_remove_keeps_tickets() {
+        let mut registry = Registry::new();
+        let (tx1, rx1) = mpsc::channel();
+        let id = registry.register(vec![1], tx1);
+        registry.dispatch(ticket("A", 1));
+
+        // The dispatcher disconnects before sending its ticket.
+        registry.remove(id, rx1.try_iter());
+        assert_eq!(registry.pending(1), 1);
+        registry.dispatch(ticket("B", 1));
+        assert_eq!(registry.pending(1), 2);
+
+        let (tx2, rx2) = mpsc::channel();
+        registry.register(vec![1], tx2);
+        assert_eq!(rx2.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_disconnected_sink() {
+        let mut registry = Registry::new();
+        let (tx1, rx1) = mpsc::channel();
+        let (tx2, rx2) = mpsc::channel();
+        registry.register(vec![1], tx1);
+        registry.register(vec![1], tx2);
+
+        // The first dispatcher went away but has not been removed yet.
+        drop(rx1);
+        registry.dispatch(ticket("A", 1));
+        assert_eq!(rx2.try_iter().collect::<Vec<_>>(), vec![ticket("A", 1)]);
+
+        drop(rx2);
+        registry.dispatch(ticket("B", 1));
+        assert_eq!(registry.pending(1), 1);
+    }
+
+    #[test]
+    fn test_introspection() {
+        let mut registry = Registry::new();
+        let camera = msg::IAmCamera {
+            road: 1,
+            mile: 8,
+            limit: 60,
+        };
+        let camera_id = registry.add_camera(camera.clone());
+        let (tx, _rx) = mpsc::channel();
+        let dispatcher_id = registry.register(vec![1, 2], tx);
+        registry.dispatch(ticket("A", 3));
+        registry.dispatch(ticket("B", 3));
+        registry.dispatch(ticket("C", 1));
+
+        assert_eq!(registry.cameras(), vec![(camera_id, &camera)]);
+        assert_eq!(registry.dispatchers(), vec![(dispatcher_id, &[1, 2][..])]);
+        assert_eq!(registry.pending_by_road(), vec![(3, 2)]);
+        let plates = registry
+            .recent()
+            .map(|t| t.plate.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(plates, vec!["A", "B", "C"]);
+
+        registry.remove_camera(camera_id);
+        assert!(registry.cameras().is_empty());
+    }
+
+    #[test]
+    fn test_recent_is_bounded() {
+        let mut registry = Registry::<mpsc::Sender<_>>::new();
+        for i in 0..RECENT_TICKETS + 1 {
+            registry.dispatch(ticket(&i.to_string(), 1));
+        }
+        assert_eq!(registry.recent().count(), RECENT_TICKETS);
+        assert_eq!(registry.recent().next().unwrap().plate, "1");
+    }
+
+    #[test]
+    fn test_client_writer() {
+        use crate::{codec, Client};
+        use std::io;
+
+        let client = Client::new(io::empty(), Vec::new()).into_dispatcher();
+        let (mut client, writer) = client.split();
+        let mut registry = Registry::new();
+        registry.register(vec![1], writer);
+        registry.dispatch(ticket("A", 1));
+        client.run_once().unwrap();
+        assert_eq!(
+            client.outbox.get_ref(),
+            &codec::to_bytes(&msg::OutgoingMessage::Ticket(ticket("A", 1))).unwrap()
+        );
+    }
+
+    // A writer whose connection has gone away.
+    struct Broken;
+
+    impl std::io::Write for Broken {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_undelivered_rerouted() {
+        use crate::outbox::FlushPolicy;
+        use crate::{codec, Client};
+        use std::io;
+
+        let broken = Client::with_flush_policy(io::empty(), Broken, FlushPolicy::PerTick);
+        let (mut broken, writer1) = broken.into_dispatcher().split();
+        let (mut client, writer2) = Client::new(io::empty(), Vec::new())
+            .into_dispatcher()
+            .split();
+        let mut registry = Registry::new();
+        let id = registry.register(vec![1], writer1);
+        registry.register(vec![1], writer2);
+
+        // The first ticket is buffered when the connection fails, and the second never leaves
+        // the queue.
+        registry.dispatch(ticket("A", 1));
+        assert!(broken.run_once().is_err());
+        registry.dispatch(ticket("B", 1));
+        registry.remove(id, broken.into_undelivered());
+
+        client.run_once().unwrap();
+        let expected = codec::to_bytes(&(
+            msg::OutgoingMessage::Ticket(ticket("A", 1)),
+            msg::OutgoingMessage::Ticket(ticket("B", 1)),
+        ))
+        .unwrap();
+        assert_eq!(client.outbox.get_ref(), &expected);
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by the unit tests. Unless a test says otherwise, everything happens to the car
+//! UN1X on road 1.
+
+use super::msg;
+use std::path::PathBuf;
+use std::{fs, process};
+
+/// A path in the temp dir for a file that doesn't exist yet, unique to this test run.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("speed-daemon-{}-{name}", process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// A camera with a limit of 60 mph.
+pub fn camera(mile: u16) -> msg::IAmCamera {
+    msg::IAmCamera {
+        road: 1,
+        mile,
+        limit: 60,
+    }
+}
+
+/// A sighting of UN1X.
+pub fn plate(timestamp: u32) -> msg::Plate {
+    msg::Plate {
+        plate: "UN1X".to_string(),
+        timestamp,
+    }
+}
+
+/// A ticket for the 100 miles between `camera(0)` and `camera(100)`.
+pub fn ticket(timestamp1: u32, timestamp2: u32, speed: u16) -> msg::Ticket {
+    msg::Ticket {
+        plate: "UN1X".to_string(),
+        road: 1,
+        mile1: 0,
+        timestamp1,
+        mile2: 100,
+        timestamp2,
+        speed,
+    }
+}
@@ -0,0 +1,268 @@
+use super::{msg, ClientWriter};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::{thread, time};
+
+/// How often the wheel turns. Heartbeat intervals are given in deciseconds, so nothing finer is
+/// needed.
+const TICK: time::Duration = time::Duration::from_millis(100);
+
+/// The number of slots in the wheel. Longer intervals go around more than once.
+const SLOTS: usize = 256;
+
+pub type HeartbeatId = usize;
+
+/// A destination for heartbeats, typically a connected client.
+pub trait HeartbeatSink {
+    fn beat(&self) -> Result<(), Box<dyn Error>>;
+}
+
+/// Heartbeats are only queued here, and written out by the client's own session on its next tick,
+/// so a client that stops reading can't hold up everyone else's heartbeats.
+impl HeartbeatSink for ClientWriter {
+    fn beat(&self) -> Result<(), Box<dyn Error>> {
+        self.send(msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+    }
+}
+
+/// A hashed timer wheel. Each slot holds the heartbeats due when the wheel reaches it, along with
+/// how many more times the wheel has to go around first.
+#[derive(Debug)]
+struct Wheel {
+    slots: Vec<Vec<(HeartbeatId, usize)>>,
+    cursor: usize,
+}
+
+impl Wheel {
+    fn new() -> Self {
+        Self {
+            slots: vec![Vec::new(); SLOTS],
+            cursor: 0,
+        }
+    }
+
+    /// Schedule a heartbeat the given number of ticks from now.
+    fn insert(&mut self, id: HeartbeatId, ticks: usize) {
+        let ticks = ticks.max(1);
+        let slot = (self.cursor + ticks) % SLOTS;
+        self.slots[slot].push((id, (ticks - 1) / SLOTS));
+    }
+
+    /// Turn the wheel by one tick, returning the heartbeats that are due.
+    fn advance(&mut self) -> Vec<HeartbeatId> {
+        self.cursor = (self.cursor + 1) % SLOTS;
+        let mut due = Vec::new();
+        self.slots[self.cursor].retain_mut(|(id, rounds)| match rounds {
+            0 => {
+                due.push(*id);
+                false
+            }
+            _ => {
+                *rounds -= 1;
+                true
+            }
+        });
+        due
+    }
+}
+
+#[derive(Debug)]
+struct State<S> {
+    wheel: Wheel,
+    next_id: HeartbeatId,
+    // The interval in ticks and where to send each heartbeat. Cancelled heartbeats are removed
+    // from here and skipped when the wheel comes around to them.
+    sinks: HashMap<HeartbeatId, (usize, S)>,
+}
+
+impl<S: Clone> State<S> {
+    /// Turn the wheel, rescheduling and returning everything that's due.
+    fn advance(&mut self) -> Vec<(HeartbeatId, S)> {
+        let mut due = Vec::new();
+        for id in self.wheel.advance() {
+            if let Some((ticks, sink)) = self.sinks.get(&id) {
+                due.push((id, sink.clone()));
+                self.wheel.insert(id, *ticks);
+            }
+        }
+        due
+    }
+}
+
+/// Sends heartbeats to every client that asked for them from a single timer thread, regardless of
+/// whether the clients are sending anything. Handles are cheap to clone, and the thread exits
+/// once they have all been dropped.
+#[derive(Debug)]
+pub struct Scheduler<S> {
+    state: Arc<Mutex<State<S>>>,
+}
+
+impl<S> Clone for Scheduler<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S: HeartbeatSink + Clone + Send + 'static> Scheduler<S> {
+    pub fn spawn() -> Self {
+        let scheduler = Self::new();
+        let state = Arc::downgrade(&scheduler.state);
+        thread::spawn(move || run(state));
+        scheduler
+    }
+}
+
+impl<S> Scheduler<S> {
+    fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                wheel: Wheel::new(),
+                next_id: 0,
+                sinks: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Send heartbeats to the sink every interval until the registration is dropped. A zero
+    /// interval means no heartbeats at all.
+    pub fn schedule(&self, interval: msg::Decisecond, sink: S) -> Registration<S> {
+        let mut state = lock(&self.state);
+        let id = state.next_id;
+        state.next_id += 1;
+        if interval.0 > 0 {
+            let ticks = interval.0 as usize;
+            state.sinks.insert(id, (ticks, sink));
+            state.wheel.insert(id, ticks);
+        }
+        Registration {
+            id,
+            state: Arc::downgrade(&self.state),
+        }
+    }
+
+    /// The number of sinks receiving heartbeats.
+    pub fn len(&self) -> usize {
+        lock(&self.state).sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Cancels a scheduled heartbeat when dropped.
+#[derive(Debug)]
+pub struct Registration<S> {
+    id: HeartbeatId,
+    state: Weak<Mutex<State<S>>>,
+}
+
+impl<S> Drop for Registration<S> {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.upgrade() {
+            lock(&state).sinks.remove(&self.id);
+        }
+    }
+}
+
+/// Turn the wheel once per tick until the scheduler is dropped. Ticks are counted from the start
+/// so time spent sending doesn't make the heartbeats drift.
+fn run<S: HeartbeatSink + Clone>(state: Weak<Mutex<State<S>>>) {
+    let start = time::Instant::now();
+    for tick in 1.. {
+        thread::sleep((start + TICK * tick).saturating_duration_since(time::Instant::now()));
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        // Sinks are called without holding the lock, so a slow client doesn't hold up others
+        // registering or leaving.
+        let due = lock(&state).advance();
+        for (id, sink) in due {
+            if sink.beat().is_err() {
+                lock(&state).sinks.remove(&id);
+            }
+        }
+    }
+}
+
+// Nothing is left half-updated by a panic while holding the lock, so poisoning can be ignored.
+fn lock<S>(state: &Mutex<State<S>>) -> MutexGuard<'_, State<S>> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    impl HeartbeatSink for mpsc::Sender<()> {
+        fn beat(&self) -> Result<(), Box<dyn Error>> {
+            Ok(self.send(())?)
+        }
+    }
+
+    #[test]
+    fn test_wheel() {
+        let mut wheel = Wheel::new();
+        wheel.insert(1, 1);
+        wheel.insert(2, 3);
+        wheel.insert(3, SLOTS + 1);
+        assert_eq!(wheel.advance(), vec![1]);
+        assert!(wheel.advance().is_empty());
+        assert_eq!(wheel.advance(), vec![2]);
+        // Goes all the way around once before it is due.
+        for _ in 4..=SLOTS {
+            assert!(wheel.advance().is_empty());
+        }
+        assert_eq!(wheel.advance(), vec![3]);
+    }
+
+    #[test]
+    fn test_reschedule() {
+        let scheduler = Scheduler::new();
+        let (tx, _rx) = mpsc::channel::<()>();
+        let _fast = scheduler.schedule(msg::Decisecond(2), tx.clone());
+        let _slow = scheduler.schedule(msg::Decisecond(3), tx);
+
+        let mut state = lock(&scheduler.state);
+        let due = (0..6)
+            .map(|_| state.advance().into_iter().map(|(id, _)| id).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            due,
+            vec![vec![], vec![0], vec![1], vec![0], vec![], vec![1, 0]]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let scheduler = Scheduler::new();
+        let (tx, _rx) = mpsc::channel::<()>();
+        let registration = scheduler.schedule(msg::Decisecond(1), tx.clone());
+        // Zero intervals are tracked but never sent.
+        let _zero = scheduler.schedule(msg::Decisecond(0), tx);
+        assert_eq!(scheduler.len(), 1);
+
+        drop(registration);
+        assert!(scheduler.is_empty());
+        assert!(lock(&scheduler.state).advance().is_empty());
+    }
+
+    #[test]
+    fn test_spawn() {
+        let scheduler = Scheduler::spawn();
+        let (tx, rx) = mpsc::channel();
+        let _registration = scheduler.schedule(msg::Decisecond(1), tx);
+        thread::sleep(TICK * 3 + TICK / 2);
+        // Allow for the test thread being scheduled late.
+        assert!((2..=4).contains(&rx.try_iter().count()));
+
+        // Sinks that fail are dropped.
+        drop(rx);
+        thread::sleep(TICK * 2);
+        assert!(scheduler.is_empty());
+    }
+}
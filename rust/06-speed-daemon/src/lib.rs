@@ -1,66 +1,104 @@
-mod codec;
-mod msg;
+// Lets code generated by the derives refer to this crate by name from inside it.
+extern crate self as speed_daemon;
+
+pub mod admin;
+pub mod async_client;
+pub mod capture;
+pub mod dispatch;
+pub mod heartbeat;
+pub mod msg;
+pub mod outbox;
+pub mod store;
+pub mod ticket;
 
-use msg::{DeserializeMessage, Message, SerializeMessage};
+#[cfg(test)]
+mod fixtures;
+
+pub use speed_daemon_codec as codec;
+
+use capture::Tap;
+use heartbeat::{Registration, Scheduler};
+use msg::Message;
+use outbox::{FlushPolicy, Outbox};
 use std::error::Error;
-use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::sync::mpsc;
-use std::{io, slice, thread, time};
+use std::{io, time};
 
 pub struct Common;
 pub struct Camera;
 pub struct Dispatcher;
 
+/// The role each kind of client plays, which decides the messages it may send.
+pub trait ClientKind {
+    type Incoming: msg::RoleMessage;
+}
+
+impl ClientKind for Common {
+    type Incoming = msg::CommonIncoming;
+}
+
+impl ClientKind for Camera {
+    type Incoming = msg::CameraIncoming;
+}
+
+impl ClientKind for Dispatcher {
+    type Incoming = msg::DispatcherIncoming;
+}
+
 #[derive(Debug)]
 pub struct Client<R: Read, W: Write, Kind = Common> {
     kind: std::marker::PhantomData<Kind>,
     rbuf: BufReader<R>,
-    wbuf: BufWriter<W>,
-    heartbeat: Option<(time::Duration, time::Instant)>,
+    outbox: Outbox<W>,
+    // Messages sent through a `ClientWriter`, which are moved to the outbox on every tick.
+    queued: mpsc::Receiver<msg::OutgoingMessage>,
+    writer: ClientWriter,
+    decoder: codec::Decoder<msg::IncomingMessage>,
+    scheduler: Option<Scheduler<ClientWriter>>,
+    heartbeat: Option<Heartbeat>,
+    capture: Option<Tap>,
 }
 
-impl<R: Read, W: Write> Client<R, W> {
-    fn next_message(&mut self) -> Result<Option<msg::IncomingMessage>, Box<dyn Error>> {
-        use msg::IncomingMessage::*;
-
-        let id = self.read_id()?;
-        // If the client is too slow to provide all the bytes of a message, an error will be
-        // returned and the order of messages possibly misaligned. That shouldn't matter though,
-        // because an error here usually means the client should disconnect.
-        id.map(|id| match id {
-            msg::WantHeartbeat::ID => Ok(WantHeartbeat(self.read_message()?)),
-            msg::IAmCamera::ID => Ok(IAmCamera(self.read_message()?)),
-            msg::IAmDispatcher::ID => Ok(IAmDispatcher(self.read_message()?)),
-            _ => Err("unrecognized message".into()),
-        })
-        .transpose()
-    }
+#[derive(Debug)]
+enum Heartbeat {
+    // Sent from `tick` whenever the client is run, as long as the period has elapsed.
+    Polled(time::Duration, time::Instant),
+    // Sent by the scheduler, until the client is dropped along with its registration.
+    Scheduled {
+        _registration: Registration<ClientWriter>,
+    },
+}
 
+impl<R: Read, W: Write> Client<R, W> {
     fn run_once(mut self) -> Result<SameOrSpecial<R, W>, Box<dyn Error>> {
         use SameOrSpecial::*;
 
-        self.send_heartbeat()?;
-        let msg = self.next_message()?;
-        if msg.is_none() {
+        Ok(match self.handle_message() {
             // Nothing to do.
-            return Ok(Same(self));
-        }
-        Ok(match msg.unwrap() {
-            msg::IncomingMessage::WantHeartbeat(want_heartbeat) => {
-                self.want_heartbeat(want_heartbeat)?;
-                Same(self)
-            }
-            msg::IncomingMessage::IAmCamera(msg) => {
+            Ok(None) => Same(self),
+            Ok(Some(msg::CommonIncoming::IAmCamera(msg))) => {
                 Special(CameraOrDispatcher::Camera(self.into_camera(), msg))
             }
-            msg::IncomingMessage::IAmDispatcher(msg) => {
+            Ok(Some(msg::CommonIncoming::IAmDispatcher(msg))) => {
                 Special(CameraOrDispatcher::Dispatcher(self.into_dispatcher(), msg))
             }
-            _ => unreachable!(),
+            Ok(Some(msg::CommonIncoming::WantHeartbeat(want_heartbeat))) => {
+                match self.want_heartbeat(want_heartbeat) {
+                    Ok(()) => Same(self),
+                    Err(err) => return Err(self.reject(err)),
+                }
+            }
+            Err(err) => return Err(self.reject(err)),
         })
     }
 
-    fn run_until_specialized(mut self) -> Result<CameraOrDispatcher<R, W>, Box<dyn Error>> {
+    fn handle_message(&mut self) -> Result<Option<msg::CommonIncoming>, Box<dyn Error>> {
+        self.tick()?;
+        self.next_message()
+    }
+
+    pub fn run_until_specialized(mut self) -> Result<CameraOrDispatcher<R, W>, Box<dyn Error>> {
         use SameOrSpecial::*;
 
         loop {
@@ -75,8 +113,13 @@ impl<R: Read, W: Write> Client<R, W> {
         Client {
             kind: std::marker::PhantomData,
             rbuf: self.rbuf,
-            wbuf: self.wbuf,
+            outbox: self.outbox,
+            queued: self.queued,
+            writer: self.writer,
+            decoder: self.decoder,
+            scheduler: self.scheduler,
             heartbeat: self.heartbeat,
+            capture: self.capture,
         }
     }
 
@@ -84,8 +127,13 @@ impl<R: Read, W: Write> Client<R, W> {
         Client {
             kind: std::marker::PhantomData,
             rbuf: self.rbuf,
-            wbuf: self.wbuf,
+            outbox: self.outbox,
+            queued: self.queued,
+            writer: self.writer,
+            decoder: self.decoder,
+            scheduler: self.scheduler,
             heartbeat: self.heartbeat,
+            capture: self.capture,
         }
     }
 }
@@ -101,107 +149,273 @@ pub enum CameraOrDispatcher<R: Read, W: Write> {
 }
 
 impl<R: Read, W: Write> Client<R, W, Camera> {
-    fn next_message(&mut self) -> Result<Option<msg::IncomingMessage>, Box<dyn Error>> {
-        use msg::IncomingMessage::*;
-
-        let id = self.read_id()?;
-        // If the client is too slow to provide all the bytes of a message, an error will be
-        // returned and the order of messages possibly misaligned. That shouldn't matter though,
-        // because an error here usually means the client should disconnect.
-        id.map(|id| match id {
-            msg::Plate::ID => Ok(Plate(self.read_message()?)),
-            msg::WantHeartbeat::ID => Ok(WantHeartbeat(self.read_message()?)),
-            _ => Err("unrecognized message".into()),
-        })
-        .transpose()
+    /// Handle a single message from the camera, passing any plate it observed to `observe`.
+    pub fn run_once(&mut self, observe: impl FnOnce(msg::PlateRef)) -> Result<(), Box<dyn Error>> {
+        self.handle_message(observe).map_err(|err| self.reject(err))
+    }
+
+    fn handle_message(
+        &mut self,
+        observe: impl FnOnce(msg::PlateRef),
+    ) -> Result<(), Box<dyn Error>> {
+        self.tick()?;
+        loop {
+            match self.decoder.peek() {
+                // Plates are nearly all a camera sends, so they're borrowed straight out of the
+                // decoder's buffer rather than being decoded into an `IncomingMessage`.
+                Some(msg::Plate::ID) => {
+                    if let Some((_, plate)) = self.decoder.decode_as::<(u8, msg::PlateRef)>()? {
+                        if let Some(tap) = &self.capture {
+                            tap.incoming(&msg::IncomingMessage::Plate(plate.into()));
+                        }
+                        observe(plate);
+                        return Ok(());
+                    }
+                }
+                Some(_) => {
+                    if let Some(msg) = self.decode_message()? {
+                        match msg.for_role()? {
+                            msg::CameraIncoming::Plate(plate) => observe((&plate).into()),
+                            msg::CameraIncoming::WantHeartbeat(want_heartbeat) => {
+                                self.want_heartbeat(want_heartbeat)?
+                            }
+                        }
+                        return Ok(());
+                    }
+                }
+                None => (),
+            }
+            if !self.fill()? {
+                return Ok(());
+            }
+        }
     }
 }
 
 impl<R: Read, W: Write> Client<R, W, Dispatcher> {
-    fn next_message(&mut self) -> Result<Option<msg::IncomingMessage>, Box<dyn Error>> {
-        use msg::IncomingMessage::*;
-
-        let id = self.read_id()?;
-        // If the client is too slow to provide all the bytes of a message, an error will be
-        // returned and the order of messages possibly misaligned. That shouldn't matter though,
-        // because an error here usually means the client should disconnect.
-        id.map(|id| match id {
-            msg::WantHeartbeat::ID => Ok(WantHeartbeat(self.read_message()?)),
-            _ => Err("unrecognized message".into()),
-        })
-        .transpose()
+    /// Send a ticket to the dispatcher.
+    pub fn send_ticket(&mut self, ticket: &msg::Ticket) -> Result<(), Box<dyn Error>> {
+        self.outbox
+            .send(&msg::OutgoingMessage::Ticket(ticket.clone()))
+    }
+
+    /// Handle a single message from the dispatcher.
+    pub fn run_once(&mut self) -> Result<(), Box<dyn Error>> {
+        self.handle_message().map_err(|err| self.reject(err))
+    }
+
+    /// The tickets written out to the dispatcher since this was last called, oldest first.
+    pub fn take_delivered(&mut self) -> Vec<msg::Ticket> {
+        self.outbox.take_delivered()
+    }
+
+    /// Disconnect, returning the tickets sent to the dispatcher that it never received: those
+    /// still queued by a `ClientWriter` as well as those that were never flushed.
+    pub fn into_undelivered(self) -> Vec<msg::Ticket> {
+        let mut tickets = self.outbox.into_unflushed();
+        tickets.extend(self.queued.try_iter().filter_map(|msg| match msg {
+            msg::OutgoingMessage::Ticket(ticket) => Some(ticket),
+            _ => None,
+        }));
+        tickets
+    }
+
+    fn handle_message(&mut self) -> Result<(), Box<dyn Error>> {
+        self.tick()?;
+        match self.next_message()? {
+            None => Ok(()),
+            Some(msg::DispatcherIncoming::WantHeartbeat(want_heartbeat)) => {
+                self.want_heartbeat(want_heartbeat)
+            }
+        }
     }
 }
 
 impl<R: Read, W: Write> Client<R, W> {
-    fn new(r: R, w: W) -> Self {
+    pub fn new(r: R, w: W) -> Self {
+        Self::with_flush_policy(r, w, FlushPolicy::default())
+    }
+
+    pub fn with_flush_policy(r: R, w: W, policy: FlushPolicy) -> Self {
+        let (tx, queued) = mpsc::channel();
         Self {
             kind: std::marker::PhantomData,
             rbuf: BufReader::new(r),
-            wbuf: BufWriter::new(w),
+            outbox: Outbox::new(w, policy),
+            queued,
+            writer: ClientWriter { tx },
+            decoder: codec::Decoder::new(),
+            scheduler: None,
             heartbeat: None,
+            capture: None,
         }
     }
+
+    /// Have the scheduler send any heartbeats the client asks for, rather than sending them when
+    /// the client happens to be run.
+    pub fn with_heartbeats(mut self, scheduler: Scheduler<ClientWriter>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Capture every message the client sends and receives.
+    pub fn with_capture(mut self, tap: Tap) -> Self {
+        self.outbox.set_capture(tap.clone());
+        self.capture = Some(tap);
+        self
+    }
 }
 
-impl<R: Read, W: Write, Kind> Client<R, W, Kind> {
+impl<R: Read, W: Write, Kind: ClientKind> Client<R, W, Kind> {
+    /// Read the next message, rejecting any the client isn't allowed to send in its role.
+    fn next_message(&mut self) -> Result<Option<Kind::Incoming>, Box<dyn Error>> {
+        match self.read_message()? {
+            Some(msg) => Ok(Some(msg.for_role()?)),
+            None => Ok(None),
+        }
+    }
+
     fn want_heartbeat(&mut self, heartbeat: msg::WantHeartbeat) -> Result<(), Box<dyn Error>> {
         if self.heartbeat.is_some() {
             return Err("heartbeat already requested".into());
         }
-        let period = heartbeat.interval.into();
-        self.heartbeat = Some((period, time::Instant::now() - period));
+        self.heartbeat = Some(match &self.scheduler {
+            Some(scheduler) => Heartbeat::Scheduled {
+                _registration: scheduler.schedule(heartbeat.interval, self.writer.clone()),
+            },
+            None => {
+                let period = heartbeat.interval.into();
+                Heartbeat::Polled(period, time::Instant::now() - period)
+            }
+        });
+        Ok(())
+    }
+
+    /// Tell the client why it is about to be disconnected.
+    fn reject(&mut self, err: Box<dyn Error>) -> Box<dyn Error> {
+        if !is_io_error(err.as_ref()) {
+            // We're disconnecting either way, so there's nothing more to do if this fails.
+            let _ = self
+                .outbox
+                .send(&msg::OutgoingMessage::Error(msg::Error(err.to_string())));
+            let _ = self.outbox.flush();
+        }
+        err
+    }
+
+    /// Send a heartbeat if one is due and move anything sent through a `ClientWriter` to the
+    /// outbox, then flush whatever the outbox is holding on to.
+    fn tick(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_heartbeat()?;
+        for msg in self.queued.try_iter() {
+            self.outbox.send(&msg)?;
+        }
+        self.outbox.tick()?;
         Ok(())
     }
 
+    /// Split off a handle for sending messages to the client from other threads. The client
+    /// itself keeps reading messages, and writes out whatever was sent on every tick.
+    pub fn split(self) -> (Self, ClientWriter) {
+        let writer = self.writer.clone();
+        (self, writer)
+    }
+
     fn send_heartbeat(&mut self) -> Result<(), Box<dyn Error>> {
         match self.heartbeat {
-            None => (),
-            Some((period, _)) if period.as_millis() == 0 => (),
-            Some((period, last)) if last.elapsed() < period => (),
-            Some((period, last)) => {
-                msg::Heartbeat.to_writer(&mut self.wbuf)?;
+            Some(Heartbeat::Polled(period, _)) if period.as_millis() == 0 => (),
+            Some(Heartbeat::Polled(period, last)) if last.elapsed() >= period => {
+                self.outbox
+                    .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))?;
                 let mut next = last;
                 while next + period < time::Instant::now() {
                     next += period;
                 }
-                self.heartbeat = Some((period, next));
+                self.heartbeat = Some(Heartbeat::Polled(period, next));
             }
+            _ => (),
         }
         Ok(())
     }
 
-    fn read_id(&mut self) -> Result<Option<u8>, Box<dyn Error>> {
-        let mut id = 0;
-        match self.rbuf.read_exact(slice::from_mut(&mut id)) {
-            Ok(_) => Ok(Some(id)),
-            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
-            #[cfg(test)]
-            // Make Eof errors act as blocking for testing.
-            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
-            Err(err) => Err(err.into()),
+    /// Read the next complete message. Returns None if the client hasn't sent one yet, in which
+    /// case any partial message is kept until the rest of it arrives.
+    fn read_message(&mut self) -> Result<Option<msg::IncomingMessage>, Box<dyn Error>> {
+        loop {
+            if let Some(msg) = self.decode_message()? {
+                return Ok(Some(msg));
+            }
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Decode the next message if all of it has already been read.
+    fn decode_message(&mut self) -> Result<Option<msg::IncomingMessage>, Box<dyn Error>> {
+        let msg = self.decoder.decode()?;
+        if let (Some(msg), Some(tap)) = (&msg, &self.capture) {
+            tap.incoming(msg);
         }
+        Ok(msg)
     }
 
-    fn read_message<'de, T: serde::Deserialize<'de>>(&mut self) -> Result<T, Box<dyn Error>> {
-        // By this point we should already have received a message ID, so reading the message
-        // shouldn't fail. A WouldBlock error means the client was too slow sending the data and we
-        // partially read the message, losing bytes and getting in an inconsistent state. Since
-        // this is soley for protohackers, this should be fine. Otherwise, a proper async library
-        // should be used.
-        let result = codec::from_reader(&mut self.rbuf);
-        if let Err(codec::Error::IOError(ref err)) = result {
-            if err.kind() == io::ErrorKind::WouldBlock {
-                panic!("timed out reading message and lost data!");
+    /// Pass whatever has arrived on to the decoder, returning false if nothing has.
+    fn fill(&mut self) -> Result<bool, Box<dyn Error>> {
+        let bytes = match self.rbuf.fill_buf() {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        if bytes.is_empty() {
+            // Make Eof errors act as blocking for testing.
+            if cfg!(test) {
+                return Ok(false);
             }
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
         }
-        Ok(result?)
+        self.decoder.push(bytes);
+        let len = bytes.len();
+        self.rbuf.consume(len);
+        Ok(true)
+    }
+}
+
+/// Whether a client was disconnected by an I/O error, including one hit while decoding. The
+/// client isn't told why in that case, since the connection is most likely gone already.
+fn is_io_error(err: &(dyn Error + 'static)) -> bool {
+    err.is::<io::Error>()
+        || matches!(
+            err.downcast_ref::<codec::Error>().map(codec::Error::kind),
+            Some(codec::Error::Io(_))
+        )
+}
+
+/// A handle for sending messages to a client from other threads. It can be cloned freely, and
+/// never touches the connection: messages are queued until the client's next tick, so sending
+/// doesn't block however slow the client is.
+#[derive(Clone, Debug)]
+pub struct ClientWriter {
+    tx: mpsc::Sender<msg::OutgoingMessage>,
+}
+
+impl ClientWriter {
+    /// Queue a message, failing if the client has gone away.
+    pub fn send(&self, msg: msg::OutgoingMessage) -> Result<(), Box<dyn Error>> {
+        self.tx.send(msg).map_err(|_| "client disconnected")?;
+        Ok(())
+    }
+
+    /// Queue a ticket for the client.
+    pub fn send_ticket(&self, ticket: &msg::Ticket) -> Result<(), Box<dyn Error>> {
+        self.send(msg::OutgoingMessage::Ticket(ticket.clone()))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::thread;
 
     #[test]
     fn common_next_message() {
@@ -226,22 +440,22 @@ mod test {
         let mut client = Client::new(&input[..], io::sink());
         assert_eq!(
             client.next_message().unwrap().unwrap(),
-            msg::IncomingMessage::WantHeartbeat(heartbeat)
+            msg::CommonIncoming::WantHeartbeat(heartbeat)
         );
         assert_eq!(
             client.next_message().unwrap().unwrap(),
-            msg::IncomingMessage::IAmCamera(camera)
+            msg::CommonIncoming::IAmCamera(camera)
         );
         assert_eq!(
             client.next_message().unwrap().unwrap(),
-            msg::IncomingMessage::IAmDispatcher(dispatcher)
+            msg::CommonIncoming::IAmDispatcher(dispatcher)
         );
         assert!(client.next_message().unwrap().is_none());
     }
 
     #[test]
     fn into_camera() {
-        let plates = vec![
+        let plates = [
             msg::Plate {
                 plate: "hello".to_string(),
                 timestamp: 1337,
@@ -265,18 +479,54 @@ mod test {
         let mut client = Client::new(&input[..], io::sink()).into_camera();
         assert_eq!(
             client.next_message().unwrap().unwrap(),
-            msg::IncomingMessage::Plate(plates[0].clone())
+            msg::CameraIncoming::Plate(plates[0].clone())
         );
         assert_eq!(
             client.next_message().unwrap().unwrap(),
-            msg::IncomingMessage::Plate(plates[1].clone())
+            msg::CameraIncoming::Plate(plates[1].clone())
         );
         assert_eq!(
             client.next_message().unwrap().unwrap(),
-            msg::IncomingMessage::Plate(plates[2].clone())
+            msg::CameraIncoming::Plate(plates[2].clone())
         );
     }
 
+    // A reader that blocks between each byte, like a socket with a read timeout.
+    struct Trickle(Vec<u8>, bool);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1 = !self.1;
+            if self.1 {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            match self.0.is_empty() {
+                true => Ok(0),
+                false => {
+                    buf[0] = self.0.remove(0);
+                    Ok(1)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_message() {
+        let plate = msg::Plate {
+            plate: "UN1X".to_string(),
+            timestamp: 1000,
+        };
+        let input = codec::to_bytes(&(msg::Plate::ID, plate.clone())).unwrap();
+        let len = input.len();
+
+        let mut client = Client::new(Trickle(input, false), io::sink()).into_camera();
+        // The reader blocks before each byte, so only the last byte completes the message.
+        for _ in 0..len {
+            assert_eq!(client.next_plate().unwrap(), None);
+        }
+        assert_eq!(client.next_plate().unwrap(), Some(plate));
+    }
+
     #[test]
     fn test_heartbeat() {
         let mut output = Vec::new();
@@ -296,6 +546,126 @@ mod test {
         assert_eq!(output, vec![msg::Heartbeat::ID; 2]);
     }
 
+    #[test]
+    fn test_heartbeat_flushed() {
+        let mut client = Client::with_flush_policy(io::empty(), Vec::new(), FlushPolicy::PerTick);
+        client
+            .want_heartbeat(msg::WantHeartbeat {
+                interval: msg::Decisecond(1),
+            })
+            .unwrap();
+        // The heartbeat is on the wire without waiting for the client to be dropped.
+        client = client.run_once().unwrap().same();
+        assert_eq!(client.outbox.get_ref(), &[msg::Heartbeat::ID]);
+    }
+
+    #[test]
+    fn test_scheduled_heartbeat() {
+        let input = codec::to_bytes(&(msg::WantHeartbeat::ID, 1_u32)).unwrap();
+        let scheduler = Scheduler::spawn();
+        let client = Client::new(&input[..], Vec::new()).with_heartbeats(scheduler.clone());
+        let client = client.run_once().unwrap().same();
+        assert_eq!(scheduler.len(), 1);
+
+        // Heartbeats are queued without the client being run, and go out on its next tick.
+        thread::sleep(time::Duration::from_millis(250));
+        assert!(client.outbox.get_ref().is_empty());
+        let client = client.run_once().unwrap().same();
+        assert!(client.outbox.get_ref().len() >= 2);
+
+        drop(client);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_tickets_coalesced() {
+        let ticket = msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 66,
+            mile1: 100,
+            timestamp1: 123456,
+            mile2: 110,
+            timestamp2: 123816,
+            speed: 10000,
+        };
+        let mut client = Client::with_flush_policy(io::empty(), Vec::new(), FlushPolicy::PerTick)
+            .into_dispatcher();
+        client.send_ticket(&ticket).unwrap();
+        client.send_ticket(&ticket).unwrap();
+        assert!(client.outbox.get_ref().is_empty());
+
+        // Both go out together on the next tick.
+        client.run_once().unwrap();
+        let expected =
+            codec::to_bytes(&((msg::Ticket::ID, ticket.clone()), (msg::Ticket::ID, ticket)))
+                .unwrap();
+        assert_eq!(client.outbox.get_ref(), &expected);
+    }
+
+    #[test]
+    fn test_split() {
+        let ticket = msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 66,
+            mile1: 100,
+            timestamp1: 123456,
+            mile2: 110,
+            timestamp2: 123816,
+            speed: 10000,
+        };
+        let input = codec::to_bytes(&(msg::WantHeartbeat::ID, 0_u32)).unwrap();
+        let client = Client::with_flush_policy(&input[..], Vec::new(), FlushPolicy::PerTick);
+        let (mut client, writer) = client.into_dispatcher().split();
+
+        // Tickets are sent from another thread while the client carries on reading.
+        let sender = {
+            let ticket = ticket.clone();
+            thread::spawn(move || writer.send_ticket(&ticket).unwrap())
+        };
+        client.run_once().unwrap();
+        sender.join().unwrap();
+        client.run_once().unwrap();
+        assert!(client.heartbeat.is_some());
+        assert_eq!(
+            client.outbox.get_ref(),
+            &codec::to_bytes(&(msg::Ticket::ID, ticket)).unwrap()
+        );
+    }
+
+    // A writer that never accepts anything, like a socket whose write timeout has expired.
+    struct Stalled;
+
+    impl Write for Stalled {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stalled_writer() {
+        let client = Client::with_flush_policy(io::empty(), Stalled, FlushPolicy::PerTick);
+        let (mut client, writer) = client.into_dispatcher().split();
+        writer
+            .send(msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        // The client is disconnected rather than left holding on to what it couldn't write.
+        let err = client.run_once().unwrap_err();
+        assert!(err.is::<io::Error>());
+    }
+
+    impl<R: Read, W: Write> Client<R, W, Camera> {
+        // Run the camera once, returning any plate it observed.
+        fn next_plate(&mut self) -> Result<Option<msg::Plate>, Box<dyn Error>> {
+            let mut observed = None;
+            self.run_once(|plate| observed = Some(plate.into()))?;
+            Ok(observed)
+        }
+    }
+
     impl<R: Read, W: Write> SameOrSpecial<R, W> {
         fn same(self) -> Client<R, W, Common> {
             match self {
@@ -326,6 +696,120 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_camera_run_once() {
+        let plate = msg::Plate {
+            plate: "UN1X".to_string(),
+            timestamp: 1000,
+        };
+        let input = codec::to_bytes(&(
+            (msg::WantHeartbeat::ID, 0_u32),
+            (msg::Plate::ID, plate.clone()),
+        ))
+        .unwrap();
+
+        let mut client = Client::new(&input[..], io::sink()).into_camera();
+        assert_eq!(client.next_plate().unwrap(), None);
+        assert!(client.heartbeat.is_some());
+        assert_eq!(client.next_plate().unwrap(), Some(plate));
+        assert_eq!(client.next_plate().unwrap(), None);
+    }
+
+    #[test]
+    fn test_send_ticket() {
+        let ticket = msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 66,
+            mile1: 100,
+            timestamp1: 123456,
+            mile2: 110,
+            timestamp2: 123816,
+            speed: 10000,
+        };
+        let mut output = Vec::new();
+        let mut client = Client::new(io::empty(), &mut output).into_dispatcher();
+        client.send_ticket(&ticket).unwrap();
+        drop(client);
+
+        assert_eq!(output, codec::to_bytes(&(msg::Ticket::ID, ticket)).unwrap());
+    }
+
+    fn error_bytes(reason: &str) -> Vec<u8> {
+        codec::to_bytes(&(msg::Error::ID, msg::Error(reason.to_string()))).unwrap()
+    }
+
+    #[test]
+    fn test_error_unrecognized() {
+        let mut output = Vec::new();
+        let client = Client::new(&[0x99_u8][..], &mut output);
+        assert!(client.run_until_specialized().is_err());
+        assert_eq!(
+            output,
+            error_bytes("unrecognized variant tag: 0x99 at byte 0")
+        );
+    }
+
+    #[test]
+    fn test_io_error() {
+        let err: Box<dyn Error> = io::Error::from(io::ErrorKind::BrokenPipe).into();
+        assert!(is_io_error(err.as_ref()));
+        // I/O errors hit while decoding are just as fatal to the connection.
+        let err: Box<dyn Error> = codec::from_bytes::<u16>(&[0]).unwrap_err().into();
+        assert!(is_io_error(err.as_ref()));
+        let err: Box<dyn Error> = codec::from_bytes::<u8>(&[0, 0]).unwrap_err().into();
+        assert!(!is_io_error(err.as_ref()));
+    }
+
+    #[test]
+    fn test_error_duplicate_heartbeat() {
+        let input = codec::to_bytes(&(
+            (msg::WantHeartbeat::ID, 10_u32),
+            (msg::WantHeartbeat::ID, 10_u32),
+        ))
+        .unwrap();
+        let mut output = Vec::new();
+        let client = Client::new(&input[..], &mut output);
+        assert!(client.run_until_specialized().is_err());
+        // The first heartbeat is due immediately.
+        let mut expected = vec![msg::Heartbeat::ID];
+        expected.extend(error_bytes("heartbeat already requested"));
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_error_unidentified_plate() {
+        let plate = msg::Plate {
+            plate: "UN1X".to_string(),
+            timestamp: 1000,
+        };
+        let input = codec::to_bytes(&(msg::Plate::ID, plate)).unwrap();
+        let mut output = Vec::new();
+        let client = Client::new(&input[..], &mut output);
+        assert!(client.run_until_specialized().is_err());
+        assert_eq!(output, error_bytes("plates can only be sent by cameras"));
+    }
+
+    #[test]
+    fn test_error_already_identified() {
+        let dispatcher = msg::IAmDispatcher { roads: vec![1] };
+        let input = codec::to_bytes(&(msg::IAmDispatcher::ID, dispatcher)).unwrap();
+
+        let mut output = Vec::new();
+        let mut client = Client::new(&input[..], &mut output).into_camera();
+        assert!(client.run_once(|_| ()).is_err());
+        drop(client);
+        assert_eq!(output, error_bytes("client already identified as a camera"));
+
+        let mut output = Vec::new();
+        let mut client = Client::new(&input[..], &mut output).into_dispatcher();
+        assert!(client.run_once().is_err());
+        drop(client);
+        assert_eq!(
+            output,
+            error_bytes("client already identified as a dispatcher")
+        );
+    }
+
     #[test]
     fn test_zero_heartbeat() {
         let mut output = Vec::new();
@@ -344,4 +828,27 @@ mod test {
 
         assert!(output.is_empty());
     }
+
+    #[test]
+    fn test_capture() {
+        let path = fixtures::temp_path("client");
+        let capture = capture::Capture::create(&path).unwrap();
+        let input = codec::to_bytes(&(msg::WantHeartbeat::ID, 0_u32, 0x99_u8)).unwrap();
+        let client = Client::new(&input[..], Vec::new()).with_capture(capture.tap());
+        let err = client.run_once().unwrap().same().run_once().err().unwrap();
+
+        // The heartbeat request went in, and the error explaining the disconnect came out.
+        let entries = capture::read(&path).unwrap();
+        let want = msg::WantHeartbeat {
+            interval: msg::Decisecond(0),
+        };
+        assert!(matches!(
+            &entries[..],
+            [
+                capture::Entry::Incoming(_, msg::IncomingMessage::WantHeartbeat(w)),
+                capture::Entry::Outgoing(_, msg::OutgoingMessage::Error(e)),
+            ] if *w == want && e.0 == err.to_string()
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -1,19 +1,188 @@
-use std::io::{BufRead, BufReader, BufWriter, Read, Write};
-use std::net::{Shutdown, TcpListener, TcpStream};
-use std::thread;
+use speed_daemon::admin;
+use speed_daemon::capture::{Capture, Tap};
+use speed_daemon::dispatch::Registry;
+use speed_daemon::heartbeat::Scheduler;
+use speed_daemon::outbox::FlushPolicy;
+use speed_daemon::store::{FileStore, NullStore, Record, Restored, SharedStore};
+use speed_daemon::ticket::{Ledger, TicketEngine};
+use speed_daemon::{CameraOrDispatcher, Client, ClientWriter};
+use std::error::Error;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::{env, io, thread, time};
+
+/// How long a read may block before the session gets a chance to do other work, like sending
+/// heartbeats.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+/// How long a write may block before the client is considered stalled and disconnected.
+const WRITE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// State shared by every session.
+struct Shared {
+    engine: TicketEngine,
+    ledger: Ledger,
+    registry: Registry<ClientWriter>,
+    store: SharedStore,
+}
+
+impl Shared {
+    /// Rebuild the state from everything in the store. Tickets that never made it to a
+    /// dispatcher are queued again.
+    fn restore(store: SharedStore) -> Result<Self, Box<dyn Error>> {
+        let records = store.lock().unwrap().records()?;
+        println!("restoring {} records", records.len());
+        let restored = Restored::from_records(records);
+        let mut registry = Registry::new();
+        for ticket in restored.pending {
+            registry.dispatch(ticket);
+        }
+        Ok(Self {
+            engine: restored.engine,
+            ledger: restored.ledger,
+            registry,
+            store,
+        })
+    }
+
+    /// Save a record. The in-memory state is still correct if this fails, so the session carries
+    /// on regardless.
+    fn record(&self, record: Record) {
+        if let Err(err) = self.store.lock().unwrap().append(&record) {
+            println!("could not save record: {err}");
+        }
+    }
+}
 
 fn main() {
+    // Nothing is kept for after a restart unless a file to keep it in is given.
+    let store: SharedStore = match env::var_os("SPEED_DAEMON_STORE") {
+        Some(path) => Arc::new(Mutex::new(
+            FileStore::open(path).expect("could not open store"),
+        )),
+        None => Arc::new(Mutex::new(NullStore)),
+    };
+    let shared = Shared::restore(store).expect("could not restore state");
+    let capture = env::var_os("SPEED_DAEMON_CAPTURE")
+        .map(|path| Capture::create(path).expect("could not create capture"));
+
     let listener = TcpListener::bind(("::", 1337)).expect("could not bind to address");
     println!("listening on :1337");
 
+    let shared = Arc::new(Mutex::new(shared));
+    if let Some(port) = env::var_os("SPEED_DAEMON_ADMIN_PORT") {
+        let port = port.to_str().and_then(|port| port.parse().ok());
+        let port = port.expect("admin port must be a number");
+        let admin = TcpListener::bind(("::", port)).expect("could not bind admin address");
+        println!("admin listening on :{port}");
+        let shared = shared.clone();
+        thread::spawn(move || serve_admin(admin, shared));
+    }
+    let heartbeats = Scheduler::spawn();
     for stream in listener.incoming().filter_map(Result::ok) {
         println!("accepted new connection");
+
+        let shared = shared.clone();
+        let heartbeats = heartbeats.clone();
+        let tap = capture.as_ref().map(Capture::tap);
+        thread::spawn(move || {
+            if let Err(err) = handle(stream, &shared, heartbeats, tap) {
+                println!("client disconnected: {err}");
+            }
+        });
+    }
+}
+
+/// Run a session for a single connection until the client disconnects or misbehaves.
+fn handle(
+    stream: TcpStream,
+    shared: &Mutex<Shared>,
+    heartbeats: Scheduler<ClientWriter>,
+    tap: Option<Tap>,
+) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let (reader, writer) = split_stream(stream)?;
+
+    // Messages are flushed at least once per poll, so they're never held back for long.
+    let mut client =
+        Client::with_flush_policy(reader, writer, FlushPolicy::PerTick).with_heartbeats(heartbeats);
+    if let Some(tap) = &tap {
+        client = client.with_capture(tap.clone());
+    }
+    match client.run_until_specialized()? {
+        CameraOrDispatcher::Camera(mut camera, info) => {
+            println!("camera connected: {info:?}");
+            let id = shared.lock().unwrap().registry.add_camera(info.clone());
+            let err = loop {
+                let result = camera.run_once(|plate| {
+                    let mut shared = shared.lock().unwrap();
+                    if let Some(tap) = &tap {
+                        tap.observed(&info, plate);
+                    }
+                    shared.record(Record::Observation(info.clone(), plate.into()));
+                    for ticket in shared.engine.observe(&info, plate) {
+                        if let Some(ticket) = shared.ledger.issue(ticket) {
+                            println!("issued ticket: {ticket:?}");
+                            shared.record(Record::Issued(ticket.clone()));
+                            shared.registry.dispatch(ticket);
+                        }
+                    }
+                });
+                if let Err(err) = result {
+                    break err;
+                }
+            };
+            shared.lock().unwrap().registry.remove_camera(id);
+            Err(err)
+        }
+        CameraOrDispatcher::Dispatcher(dispatcher, info) => {
+            println!("dispatcher connected: {info:?}");
+            // Tickets are queued for the dispatcher by whichever camera session issued them, while
+            // this session keeps reading and writes them out on every tick.
+            let (mut dispatcher, writer) = dispatcher.split();
+            let id = shared.lock().unwrap().registry.register(info.roads, writer);
+
+            let err = loop {
+                let result = dispatcher.run_once();
+                // Tickets only count as delivered once they've been flushed, so any lost along
+                // with the connection are queued again after a restart.
+                let delivered = dispatcher.take_delivered();
+                if !delivered.is_empty() {
+                    let shared = shared.lock().unwrap();
+                    for ticket in delivered {
+                        shared.record(Record::Delivered(ticket));
+                    }
+                }
+                if let Err(err) = result {
+                    break err;
+                }
+            };
+            // Tickets the dispatcher never received go to another one, or wait for one to connect.
+            // The lock is held throughout, so nothing more can be queued for it in the meantime.
+            let mut shared = shared.lock().unwrap();
+            let undelivered = dispatcher.into_undelivered();
+            shared.registry.remove(id, undelivered);
+            Err(err)
+        }
+    }
+}
+
+/// Answer admin commands on every connection to the listener.
+fn serve_admin(listener: TcpListener, shared: Arc<Mutex<Shared>>) {
+    for stream in listener.incoming().filter_map(Result::ok) {
+        let shared = shared.clone();
+        thread::spawn(move || {
+            let (reader, writer) = split_stream(stream)?;
+            admin::serve(io::BufReader::new(reader), writer, |command| {
+                admin::respond(command, &shared.lock().unwrap().registry)
+            })
+        });
     }
 }
 
-/// Split a TcpStream into a buffered reader and writer.
-fn split_stream(stream: TcpStream) -> (BufReader<TcpStream>, BufWriter<TcpStream>) {
-    let reader = BufReader::new(stream.try_clone().unwrap());
-    let writer = BufWriter::new(stream);
-    (reader, writer)
+/// Split a TcpStream into a reader and writer. Buffering is left to the Client.
+fn split_stream(stream: TcpStream) -> io::Result<(TcpStream, TcpStream)> {
+    let reader = stream.try_clone()?;
+    Ok((reader, stream))
 }
@@ -1,31 +1,76 @@
 use super::codec;
+use speed_daemon_derive::{Message, MessageEnum};
 use std::io::{Read, Write};
-use std::time;
+use std::{fmt, time};
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
-pub struct Error(String);
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x10, sent_by(server))]
+pub struct Error(pub String);
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+/// An `Error` that borrows its message from the input.
+#[derive(Copy, Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(like = Error)]
+#[serde(rename = "Error")]
+pub struct ErrorRef<'a>(pub &'a str);
+
+impl From<ErrorRef<'_>> for Error {
+    fn from(error: ErrorRef<'_>) -> Self {
+        Error(error.0.to_string())
+    }
+}
+
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x21, sent_by(server))]
 pub struct Ticket {
     pub plate: String,
     pub road: u16,
-    pub mile1: u32,
+    pub mile1: u16,
     pub timestamp1: u32,
-    pub mile2: u32,
+    pub mile2: u16,
     pub timestamp2: u32,
     pub speed: u16,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x41, sent_by(server))]
 pub struct Heartbeat;
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x20, sent_by(camera))]
 pub struct Plate {
     pub plate: String,
     pub timestamp: u32,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+/// A `Plate` that borrows the plate from the input, so decoding one doesn't allocate.
+#[derive(Copy, Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(like = Plate)]
+#[serde(rename = "Plate")]
+pub struct PlateRef<'a> {
+    pub plate: &'a str,
+    pub timestamp: u32,
+}
+
+impl From<PlateRef<'_>> for Plate {
+    fn from(plate: PlateRef<'_>) -> Self {
+        Plate {
+            plate: plate.plate.to_string(),
+            timestamp: plate.timestamp,
+        }
+    }
+}
+
+impl<'a> From<&'a Plate> for PlateRef<'a> {
+    fn from(plate: &'a Plate) -> Self {
+        PlateRef {
+            plate: &plate.plate,
+            timestamp: plate.timestamp,
+        }
+    }
+}
+
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x40, sent_by(unidentified, camera, dispatcher))]
 pub struct WantHeartbeat {
     pub interval: Decisecond,
 }
@@ -39,20 +84,66 @@ impl From<Decisecond> for time::Duration {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x80, sent_by(unidentified))]
 pub struct IAmCamera {
     pub road: u16,
     pub mile: u16,
     pub limit: u16,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Message, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[message(id = 0x81, sent_by(unidentified))]
 pub struct IAmDispatcher {
     pub roads: Vec<u16>,
 }
 
+/// Who sends a message: the server, or a client in one of the roles it can take on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// A client that hasn't yet said whether it's a camera or a dispatcher.
+    Unidentified,
+    Camera,
+    Dispatcher,
+    Server,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::Unidentified => "unidentified client",
+            Role::Camera => "camera",
+            Role::Dispatcher => "dispatcher",
+            Role::Server => "server",
+        })
+    }
+}
+
+/// A message type, usually implemented with `#[derive(Message)]`.
 pub trait Message {
     const ID: u8;
+    /// The roles allowed to send the message.
+    const SENT_BY: &'static [Role];
+}
+
+/// An enum of just the messages a client in one role may send.
+pub trait RoleMessage:
+    TryFrom<IncomingMessage, Error = IncomingMessage> + Into<IncomingMessage>
+{
+    const ROLE: Role;
+}
+
+/// Whether a role is in a list of roles, in a way that can be checked at compile time.
+#[doc(hidden)]
+pub const fn sends(roles: &[Role], role: Role) -> bool {
+    let mut i = 0;
+    while i < roles.len() {
+        if roles[i] as u8 == role as u8 {
+            return true;
+        }
+        i += 1;
+    }
+    false
 }
 
 pub trait SerializeMessage: Message + serde::Serialize {
@@ -63,16 +154,25 @@ pub trait SerializeMessage: Message + serde::Serialize {
 }
 
 pub trait DeserializeMessage<'de>: Message + serde::Deserialize<'de> {
-    fn from_reader<R: Read>(&self, r: R) -> Result<Self, Box<dyn std::error::Error>> {
+    fn from_reader<R: Read>(r: R) -> Result<Self, Box<dyn std::error::Error>> {
         let (id, t): (u8, Self) = codec::from_reader(r)?;
         if id != Self::ID {
             return Err("wrong ID".into());
         }
         Ok(t)
     }
+
+    fn from_bytes(bytes: &'de [u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let (id, t): (u8, Self) = codec::from_bytes(bytes)?;
+        if id != Self::ID {
+            return Err("wrong ID".into());
+        }
+        Ok(t)
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+// Variants are tagged with the ID of the message they hold.
+#[derive(Clone, Debug, PartialEq, Eq, MessageEnum)]
 pub enum IncomingMessage {
     IAmCamera(IAmCamera),
     IAmDispatcher(IAmDispatcher),
@@ -80,91 +180,61 @@ pub enum IncomingMessage {
     Plate(Plate),
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum OutgoingMessage {
-    Heartbeat,
-    Error(Error),
-    Ticket(Ticket),
+/// Messages a client may send before identifying itself.
+#[derive(Clone, Debug, PartialEq, Eq, MessageEnum)]
+#[message(role = unidentified, subset_of = IncomingMessage)]
+pub enum CommonIncoming {
+    IAmCamera(IAmCamera),
+    IAmDispatcher(IAmDispatcher),
+    WantHeartbeat(WantHeartbeat),
 }
 
-macro_rules! impl_message {
-    ($t:ty = $value:expr) => {
-        impl SerializeMessage for $t {}
-        impl DeserializeMessage<'_> for $t {}
-        impl Message for $t {
-            const ID: u8 = $value;
-        }
-    };
+/// Messages a camera may send.
+#[derive(Clone, Debug, PartialEq, Eq, MessageEnum)]
+#[message(role = camera, subset_of = IncomingMessage)]
+pub enum CameraIncoming {
+    WantHeartbeat(WantHeartbeat),
+    Plate(Plate),
 }
 
-impl_message!(Error = 0x10);
-impl_message!(Plate = 0x20);
-impl_message!(Ticket = 0x21);
-impl_message!(WantHeartbeat = 0x40);
-impl_message!(Heartbeat = 0x41);
-impl_message!(IAmCamera = 0x80);
-impl_message!(IAmDispatcher = 0x81);
-
-// Example implementation of deserializing an enum.
-#[cfg(test)]
-mod test {
-    use super::*;
+/// Messages a dispatcher may send.
+#[derive(Clone, Debug, PartialEq, Eq, MessageEnum)]
+#[message(role = dispatcher, subset_of = IncomingMessage)]
+pub enum DispatcherIncoming {
+    WantHeartbeat(WantHeartbeat),
+}
 
-    #[derive(Debug, PartialEq, Eq)]
-    pub enum IncomingMessage {
-        IAmCamera(IAmCamera),
-        IAmDispatcher(IAmDispatcher),
-        WantHeartbeat(WantHeartbeat),
-        Plate(Plate),
+impl IncomingMessage {
+    /// Narrow the message down to one a client in the role may send, explaining why not if it
+    /// isn't.
+    pub fn for_role<M: RoleMessage>(self) -> Result<M, Box<dyn std::error::Error>> {
+        M::try_from(self).map_err(|msg| msg.refusal(M::ROLE))
     }
 
-    impl<'de> serde::Deserialize<'de> for IncomingMessage {
-        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-        where
-            D: serde::Deserializer<'de>,
-        {
-            struct Visitor;
-
-            impl<'de> serde::de::Visitor<'de> for Visitor {
-                type Value = IncomingMessage;
-
-                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                    formatter.write_str("tuple")
-                }
-
-                fn visit_seq<V>(self, mut seq: V) -> Result<IncomingMessage, V::Error>
-                where
-                    V: serde::de::SeqAccess<'de>,
-                {
-                    let id: u8 = seq
-                        .next_element()?
-                        .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-                    Ok(match id {
-                        IAmCamera::ID => IncomingMessage::IAmCamera(
-                            seq.next_element()?
-                                .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?,
-                        ),
-                        IAmDispatcher::ID => IncomingMessage::IAmDispatcher(
-                            seq.next_element()?
-                                .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?,
-                        ),
-                        WantHeartbeat::ID => IncomingMessage::WantHeartbeat(
-                            seq.next_element()?
-                                .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?,
-                        ),
-                        Plate::ID => IncomingMessage::Plate(
-                            seq.next_element()?
-                                .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?,
-                        ),
-                        _ => {
-                            return Err(serde::de::Error::custom(format!("unrecognized id: {id}")))
-                        }
-                    })
-                }
-            }
-            deserializer.deserialize_tuple(2, Visitor)
+    // Why a client in the role isn't allowed to send the message.
+    fn refusal(&self, role: Role) -> Box<dyn std::error::Error> {
+        // Anything only unidentified clients can send is how they identify themselves.
+        if self.allowed_from(Role::Unidentified) {
+            return format!("client already identified as a {role}").into();
         }
+        let senders = self.sent_by().iter().map(|role| format!("{role}s"));
+        let senders = senders.collect::<Vec<_>>().join(" or ");
+        let name = self.name().to_lowercase();
+        format!("{name}s can only be sent by {senders}").into()
     }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, MessageEnum)]
+pub enum OutgoingMessage {
+    Heartbeat(Heartbeat),
+    Error(Error),
+    Ticket(Ticket),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_deserialize_enum() {
@@ -181,4 +251,458 @@ mod test {
         });
         assert_eq!(expected, codec::from_bytes(&bytes[..]).unwrap());
     }
+
+    #[test]
+    fn test_error_position() {
+        let bytes = [
+            0x81, // IAmDispatcher ID
+            4,    // numroads
+            0x00, 0x01, 0x00, 0x02, 0x00, 0x03, // roads
+            0x00, // truncated road
+        ];
+        let err = codec::from_bytes::<IncomingMessage>(&bytes).unwrap_err();
+        let position = err.position().unwrap();
+        assert_eq!(position.offset, 8);
+        assert_eq!(position.id, Some(IAmDispatcher::ID));
+        assert_eq!(position.path, "IAmDispatcher.roads[3]");
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let plate = Plate {
+            plate: "UN1X".to_string(),
+            timestamp: 1000,
+        };
+        let mut bytes = Vec::new();
+        plate.to_writer(&mut bytes).unwrap();
+        let borrowed = PlateRef::from_bytes(&bytes).unwrap();
+        assert_eq!(borrowed.plate, "UN1X");
+        assert!(bytes.as_ptr_range().contains(&borrowed.plate.as_ptr()));
+        assert_eq!(Plate::from(borrowed), plate);
+
+        // Borrowed views encode just like the messages they borrow from.
+        let mut expected = Vec::new();
+        Error("bad".to_string()).to_writer(&mut expected).unwrap();
+        let mut bytes = Vec::new();
+        ErrorRef("bad").to_writer(&mut bytes).unwrap();
+        assert_eq!(bytes, expected);
+        assert_eq!(ErrorRef::from_bytes(&bytes).unwrap(), ErrorRef("bad"));
+
+        // Nothing can be borrowed from a reader.
+        assert!(PlateRef::from_reader(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_enum_ids() {
+        let camera = IAmCamera {
+            road: 1,
+            mile: 2,
+            limit: 3,
+        };
+        let dispatcher = IAmDispatcher { roads: vec![1, 2] };
+        let want_heartbeat = WantHeartbeat {
+            interval: Decisecond(10),
+        };
+        let plate = Plate {
+            plate: "UN1X".to_string(),
+            timestamp: 1000,
+        };
+        let error = Error("bad".to_string());
+        let ticket = Ticket {
+            plate: "UN1X".to_string(),
+            road: 1,
+            mile1: 8,
+            timestamp1: 0,
+            mile2: 9,
+            timestamp2: 45,
+            speed: 8000,
+        };
+
+        // Every variant is tagged with the ID of the message it holds.
+        let pairs = [
+            (
+                codec::to_bytes(&IncomingMessage::IAmCamera(camera.clone())),
+                codec::to_bytes(&(IAmCamera::ID, camera)),
+            ),
+            (
+                codec::to_bytes(&IncomingMessage::IAmDispatcher(dispatcher.clone())),
+                codec::to_bytes(&(IAmDispatcher::ID, dispatcher)),
+            ),
+            (
+                codec::to_bytes(&IncomingMessage::WantHeartbeat(want_heartbeat.clone())),
+                codec::to_bytes(&(WantHeartbeat::ID, want_heartbeat)),
+            ),
+            (
+                codec::to_bytes(&IncomingMessage::Plate(plate.clone())),
+                codec::to_bytes(&(Plate::ID, plate)),
+            ),
+            (
+                codec::to_bytes(&OutgoingMessage::Heartbeat(Heartbeat)),
+                codec::to_bytes(&(Heartbeat::ID, Heartbeat)),
+            ),
+            (
+                codec::to_bytes(&OutgoingMessage::Error(error.clone())),
+                codec::to_bytes(&(Error::ID, error)),
+            ),
+            (
+                codec::to_bytes(&OutgoingMessage::Ticket(ticket.clone())),
+                codec::to_bytes(&(Ticket::ID, ticket)),
+            ),
+        ];
+        for (variant, message) in pairs {
+            assert_eq!(variant.unwrap(), message.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_derived() {
+        assert_eq!(PlateRef::ID, Plate::ID);
+        assert_eq!(ErrorRef::SENT_BY, [Role::Server]);
+
+        let msg = IncomingMessage::WantHeartbeat(WantHeartbeat {
+            interval: Decisecond(10),
+        });
+        assert_eq!(msg.id(), WantHeartbeat::ID);
+        assert_eq!(msg.name(), "WantHeartbeat");
+        assert!(msg.allowed_from(Role::Camera));
+        assert!(!msg.allowed_from(Role::Server));
+    }
+
+    fn incoming() -> Vec<IncomingMessage> {
+        vec![
+            IncomingMessage::IAmCamera(IAmCamera {
+                road: 1,
+                mile: 2,
+                limit: 3,
+            }),
+            IncomingMessage::IAmDispatcher(IAmDispatcher { roads: vec![1] }),
+            IncomingMessage::WantHeartbeat(WantHeartbeat {
+                interval: Decisecond(10),
+            }),
+            IncomingMessage::Plate(Plate {
+                plate: "UN1X".to_string(),
+                timestamp: 1000,
+            }),
+        ]
+    }
+
+    // Every message a role may send converts to its enum and back, and nothing else converts.
+    fn check_role<M: RoleMessage + fmt::Debug>() {
+        for msg in incoming() {
+            let allowed = msg.allowed_from(M::ROLE);
+            match M::try_from(msg.clone()) {
+                Ok(narrowed) => {
+                    assert!(allowed, "{narrowed:?} isn't sent by {}s", M::ROLE);
+                    assert_eq!(narrowed.into(), msg);
+                }
+                Err(rejected) => {
+                    assert!(!allowed, "{rejected:?} is missing for {}s", M::ROLE);
+                    assert_eq!(rejected, msg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_roles() {
+        check_role::<CommonIncoming>();
+        check_role::<CameraIncoming>();
+        check_role::<DispatcherIncoming>();
+    }
+
+    #[test]
+    fn test_for_role() {
+        let [camera, _, _, plate] = <[_; 4]>::try_from(incoming()).unwrap();
+        assert!(plate.clone().for_role::<CameraIncoming>().is_ok());
+        assert!(camera.clone().for_role::<CommonIncoming>().is_ok());
+
+        let reason = plate.for_role::<DispatcherIncoming>().unwrap_err();
+        assert_eq!(reason.to_string(), "plates can only be sent by cameras");
+        let reason = camera.for_role::<DispatcherIncoming>().unwrap_err();
+        assert_eq!(
+            reason.to_string(),
+            "client already identified as a dispatcher"
+        );
+    }
+
+    // Strategies for generating every message, covering the full range of each field.
+    fn arb_ascii() -> impl Strategy<Value = String> {
+        prop::collection::vec(0..=0x7f_u8, 0..=255).prop_map(|b| String::from_utf8(b).unwrap())
+    }
+
+    fn arb_ticket() -> impl Strategy<Value = Ticket> {
+        let fields = (any::<u16>(), any::<u32>(), any::<u16>(), any::<u32>());
+        (arb_ascii(), any::<u16>(), fields, any::<u16>()).prop_map(
+            |(plate, road, (mile1, timestamp1, mile2, timestamp2), speed)| Ticket {
+                plate,
+                road,
+                mile1,
+                timestamp1,
+                mile2,
+                timestamp2,
+                speed,
+            },
+        )
+    }
+
+    fn arb_incoming() -> impl Strategy<Value = IncomingMessage> {
+        prop_oneof![
+            (any::<u16>(), any::<u16>(), any::<u16>()).prop_map(|(road, mile, limit)| {
+                IncomingMessage::IAmCamera(IAmCamera { road, mile, limit })
+            }),
+            prop::collection::vec(any::<u16>(), 0..=255)
+                .prop_map(|roads| IncomingMessage::IAmDispatcher(IAmDispatcher { roads })),
+            any::<u32>().prop_map(|interval| {
+                IncomingMessage::WantHeartbeat(WantHeartbeat {
+                    interval: Decisecond(interval),
+                })
+            }),
+            (arb_ascii(), any::<u32>())
+                .prop_map(|(plate, timestamp)| IncomingMessage::Plate(Plate { plate, timestamp })),
+        ]
+    }
+
+    fn arb_outgoing() -> impl Strategy<Value = OutgoingMessage> {
+        prop_oneof![
+            Just(OutgoingMessage::Heartbeat(Heartbeat)),
+            arb_ascii().prop_map(|msg| OutgoingMessage::Error(Error(msg))),
+            arb_ticket().prop_map(OutgoingMessage::Ticket),
+        ]
+    }
+
+    // The message decodes to itself both on its own and as part of its enum, from a slice and
+    // from a reader.
+    fn check_round_trip<M, E>(msg: M, variant: fn(M) -> E) -> Result<(), TestCaseError>
+    where
+        M: SerializeMessage + for<'de> DeserializeMessage<'de> + Clone + PartialEq + fmt::Debug,
+        E: serde::Serialize + for<'de> serde::Deserialize<'de> + PartialEq + fmt::Debug,
+    {
+        let mut bytes = Vec::new();
+        msg.to_writer(&mut bytes).unwrap();
+        prop_assert_eq!(M::from_bytes(&bytes).unwrap(), msg.clone());
+        prop_assert_eq!(M::from_reader(&bytes[..]).unwrap(), msg.clone());
+
+        let variant = variant(msg);
+        prop_assert_eq!(&codec::to_bytes(&variant).unwrap(), &bytes);
+        prop_assert_eq!(codec::from_bytes::<E>(&bytes).unwrap(), variant);
+        Ok(())
+    }
+
+    // Cutting a message short anywhere is an unexpected end of input, however it's read.
+    fn check_truncated<E>(bytes: &[u8], index: prop::sample::Index) -> Result<(), TestCaseError>
+    where
+        E: for<'de> serde::Deserialize<'de> + fmt::Debug,
+    {
+        let truncated = &bytes[..index.index(bytes.len())];
+        for err in [
+            codec::from_bytes::<E>(truncated).unwrap_err(),
+            codec::from_reader::<_, E>(truncated).unwrap_err(),
+        ] {
+            prop_assert!(
+                matches!(err.kind(), codec::Error::Io(io) if io.kind() == std::io::ErrorKind::UnexpectedEof),
+                "{err}"
+            );
+        }
+        let mut decoder = codec::Decoder::<E>::new();
+        decoder.push(truncated);
+        prop_assert!(decoder.decode().unwrap().is_none());
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip_incoming(msg in arb_incoming()) {
+            match msg {
+                IncomingMessage::IAmCamera(msg) => check_round_trip(msg, IncomingMessage::IAmCamera)?,
+                IncomingMessage::IAmDispatcher(msg) => check_round_trip(msg, IncomingMessage::IAmDispatcher)?,
+                IncomingMessage::WantHeartbeat(msg) => check_round_trip(msg, IncomingMessage::WantHeartbeat)?,
+                IncomingMessage::Plate(msg) => check_round_trip(msg, IncomingMessage::Plate)?,
+            }
+        }
+
+        #[test]
+        fn test_round_trip_outgoing(msg in arb_outgoing()) {
+            match msg {
+                OutgoingMessage::Heartbeat(msg) => check_round_trip(msg, OutgoingMessage::Heartbeat)?,
+                OutgoingMessage::Error(msg) => check_round_trip(msg, OutgoingMessage::Error)?,
+                OutgoingMessage::Ticket(msg) => check_round_trip(msg, OutgoingMessage::Ticket)?,
+            }
+        }
+
+        #[test]
+        fn test_truncated_incoming(msg in arb_incoming(), index in any::<prop::sample::Index>()) {
+            check_truncated::<IncomingMessage>(&codec::to_bytes(&msg).unwrap(), index)?;
+        }
+
+        #[test]
+        fn test_truncated_outgoing(msg in arb_outgoing(), index in any::<prop::sample::Index>()) {
+            check_truncated::<OutgoingMessage>(&codec::to_bytes(&msg).unwrap(), index)?;
+        }
+
+        #[test]
+        fn test_non_ascii_plate(
+            plate in arb_ascii().prop_filter("needs a byte to replace", |p| !p.is_empty()),
+            index in any::<prop::sample::Index>(),
+            byte in 0x80_u8..,
+        ) {
+            let msg = Plate { plate, timestamp: 0 };
+            let mut bytes = Vec::new();
+            msg.to_writer(&mut bytes).unwrap();
+            // The ID and length come before the plate itself.
+            bytes[2 + index.index(msg.plate.len())] = byte;
+            let err = codec::from_bytes::<IncomingMessage>(&bytes).unwrap_err();
+            prop_assert!(matches!(err.kind(), codec::Error::ExpectedAsciiCharacter), "{err}");
+            prop_assert_eq!(err.position().unwrap().offset, 1);
+            prop_assert_eq!(&err.position().unwrap().path, "Plate.plate");
+
+            // Nor will the codec encode one.
+            let mut plate = msg.plate;
+            plate.insert(index.index(plate.len()), 'é');
+            let err = codec::to_bytes(&Plate { plate, timestamp: 0 }).unwrap_err();
+            prop_assert!(matches!(err, codec::Error::ExpectedAsciiCharacter), "{err}");
+        }
+
+        #[test]
+        fn test_unknown_id(id in any::<u8>(), rest in prop::collection::vec(any::<u8>(), 0..16)) {
+            let known = [IAmCamera::ID, IAmDispatcher::ID, WantHeartbeat::ID, Plate::ID];
+            prop_assume!(!known.contains(&id));
+            let bytes = [&[id][..], &rest].concat();
+            let err = codec::from_bytes::<IncomingMessage>(&bytes).unwrap_err();
+            prop_assert!(matches!(err.kind(), codec::Error::UnknownVariantTag(tag) if *tag == id));
+        }
+
+        #[test]
+        fn test_string_too_long(len in 256_usize..1024) {
+            let err = codec::to_bytes(&Error("a".repeat(len))).unwrap_err();
+            prop_assert!(matches!(err, codec::Error::StringTooLong(255)), "{err}");
+        }
+    }
+
+    // Frames from the spec's examples, by name.
+    fn spec_vectors() -> Vec<(&'static str, Vec<u8>)> {
+        include_str!("../testdata/spec.txt")
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut words = line.split_whitespace();
+                let name = words.next().unwrap();
+                let bytes = words.map(|b| u8::from_str_radix(b, 16).unwrap());
+                (name, bytes.collect())
+            })
+            .collect()
+    }
+
+    // Each frame decodes to the expected message, which encodes back to exactly the same bytes.
+    fn check_vectors<E>(expected: &[(&str, E)], vectors: &[(&str, Vec<u8>)])
+    where
+        E: serde::Serialize + for<'de> serde::Deserialize<'de> + PartialEq + fmt::Debug,
+    {
+        for (name, msg) in expected {
+            let (_, bytes) = vectors
+                .iter()
+                .find(|(n, _)| n == name)
+                .unwrap_or_else(|| panic!("no frame named {name}"));
+            assert_eq!(codec::from_bytes::<E>(bytes).unwrap(), *msg, "{name}");
+            assert_eq!(codec::to_bytes(msg).unwrap(), *bytes, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_spec_vectors() {
+        let incoming = [
+            (
+                "plate-un1x",
+                IncomingMessage::Plate(Plate {
+                    plate: "UN1X".to_string(),
+                    timestamp: 1000,
+                }),
+            ),
+            (
+                "plate-re05bkg",
+                IncomingMessage::Plate(Plate {
+                    plate: "RE05BKG".to_string(),
+                    timestamp: 123456,
+                }),
+            ),
+            (
+                "want-heartbeat-10",
+                IncomingMessage::WantHeartbeat(WantHeartbeat {
+                    interval: Decisecond(10),
+                }),
+            ),
+            (
+                "want-heartbeat-1243",
+                IncomingMessage::WantHeartbeat(WantHeartbeat {
+                    interval: Decisecond(1243),
+                }),
+            ),
+            (
+                "camera-66",
+                IncomingMessage::IAmCamera(IAmCamera {
+                    road: 66,
+                    mile: 100,
+                    limit: 60,
+                }),
+            ),
+            (
+                "camera-368",
+                IncomingMessage::IAmCamera(IAmCamera {
+                    road: 368,
+                    mile: 1234,
+                    limit: 40,
+                }),
+            ),
+            (
+                "dispatcher-one-road",
+                IncomingMessage::IAmDispatcher(IAmDispatcher { roads: vec![66] }),
+            ),
+            (
+                "dispatcher-three-roads",
+                IncomingMessage::IAmDispatcher(IAmDispatcher {
+                    roads: vec![66, 368, 5000],
+                }),
+            ),
+        ];
+        let outgoing = [
+            (
+                "error-bad",
+                OutgoingMessage::Error(Error("bad".to_string())),
+            ),
+            (
+                "error-illegal-msg",
+                OutgoingMessage::Error(Error("illegal msg".to_string())),
+            ),
+            (
+                "ticket-un1x",
+                OutgoingMessage::Ticket(Ticket {
+                    plate: "UN1X".to_string(),
+                    road: 66,
+                    mile1: 100,
+                    timestamp1: 123456,
+                    mile2: 110,
+                    timestamp2: 123816,
+                    speed: 10000,
+                }),
+            ),
+            (
+                "ticket-re05bkg",
+                OutgoingMessage::Ticket(Ticket {
+                    plate: "RE05BKG".to_string(),
+                    road: 368,
+                    mile1: 1234,
+                    timestamp1: 1000000,
+                    mile2: 1235,
+                    timestamp2: 1000060,
+                    speed: 6000,
+                }),
+            ),
+            ("heartbeat", OutgoingMessage::Heartbeat(Heartbeat)),
+        ];
+
+        let vectors = spec_vectors();
+        check_vectors(&incoming, &vectors);
+        check_vectors(&outgoing, &vectors);
+        // Every frame in the corpus is checked.
+        assert_eq!(vectors.len(), incoming.len() + outgoing.len());
+    }
 }
@@ -0,0 +1,304 @@
+use super::capture::Tap;
+use super::{codec, msg};
+use std::error::Error;
+use std::io::{self, Write};
+
+/// When buffered messages are written out to the connection.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush after every message.
+    #[default]
+    PerMessage,
+    /// Coalesce messages and flush them once per tick of the client's loop.
+    PerTick,
+    /// Flush as soon as at least this many bytes are buffered, and otherwise once per tick.
+    Threshold(usize),
+}
+
+/// Buffers outgoing messages and writes them to the connection according to a `FlushPolicy`, so
+/// nothing sits in the buffer longer than a tick.
+#[derive(Debug)]
+pub struct Outbox<W: Write> {
+    writer: W,
+    // Bytes that haven't been written to the connection yet. These are written out by hand
+    // rather than through a `BufWriter`, so it's known exactly which tickets made it out.
+    wbuf: Vec<u8>,
+    policy: FlushPolicy,
+    capture: Option<Tap>,
+    // Tickets that haven't been written whole yet along with where in `wbuf` they end, so they
+    // can go to another dispatcher if the connection is lost first, and those written since they
+    // were last taken.
+    unflushed: Vec<(usize, msg::Ticket)>,
+    delivered: Vec<msg::Ticket>,
+}
+
+impl<W: Write> Outbox<W> {
+    pub fn new(w: W, policy: FlushPolicy) -> Self {
+        Self {
+            writer: w,
+            wbuf: Vec::new(),
+            policy,
+            capture: None,
+            unflushed: Vec::new(),
+            delivered: Vec::new(),
+        }
+    }
+
+    /// Capture every message sent from now on. Tickets are captured as they're written out
+    /// rather than as they're queued.
+    pub fn set_capture(&mut self, tap: Tap) {
+        self.capture = Some(tap);
+    }
+
+    /// Queue a message, flushing it right away if the policy calls for it.
+    pub fn send(&mut self, msg: &msg::OutgoingMessage) -> Result<(), Box<dyn Error>> {
+        // Encode first, so a message that fails to encode leaves nothing behind in the buffer.
+        let bytes = codec::to_bytes(msg)?;
+        self.wbuf.extend_from_slice(&bytes);
+        match (msg, &self.capture) {
+            (msg::OutgoingMessage::Ticket(ticket), _) => {
+                self.unflushed.push((self.wbuf.len(), ticket.clone()));
+            }
+            (msg, Some(tap)) => tap.outgoing(msg),
+            (_, None) => (),
+        }
+        match self.policy {
+            FlushPolicy::PerMessage => self.flush()?,
+            FlushPolicy::Threshold(threshold) if self.buffered() >= threshold => self.flush()?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Flush anything still buffered. This should be called on every iteration of the client's
+    /// loop.
+    pub fn tick(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    /// Write everything buffered out to the connection. If that fails part way through, whatever
+    /// was written is dropped from the buffer, and any tickets written whole count as delivered.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.wbuf.len() {
+                break self.writer.flush();
+            }
+            match self.writer.write(&self.wbuf[written..]) {
+                Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => break Err(err),
+            }
+        };
+        self.wbuf.drain(..written);
+        let done = self
+            .unflushed
+            .iter()
+            .take_while(|(end, _)| *end <= written)
+            .count();
+        for (_, ticket) in self.unflushed.drain(..done) {
+            if let Some(tap) = &self.capture {
+                tap.outgoing(&msg::OutgoingMessage::Ticket(ticket.clone()));
+            }
+            self.delivered.push(ticket);
+        }
+        for (end, _) in &mut self.unflushed {
+            *end -= written;
+        }
+        result
+    }
+
+    /// The tickets written out since this was last called, oldest first.
+    pub fn take_delivered(&mut self) -> Vec<msg::Ticket> {
+        std::mem::take(&mut self.delivered)
+    }
+
+    /// The number of bytes waiting to be flushed.
+    pub fn buffered(&self) -> usize {
+        self.wbuf.len()
+    }
+
+    /// Give up on the connection, returning the tickets that were never written whole. Nothing
+    /// else is written, so they can be sent elsewhere without being delivered twice.
+    pub fn into_unflushed(self) -> Vec<msg::Ticket> {
+        self.unflushed
+            .into_iter()
+            .map(|(_, ticket)| ticket)
+            .collect()
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ticket() -> msg::OutgoingMessage {
+        msg::OutgoingMessage::Ticket(msg::Ticket {
+            plate: "UN1X".to_string(),
+            road: 66,
+            mile1: 100,
+            timestamp1: 123456,
+            mile2: 110,
+            timestamp2: 123816,
+            speed: 10000,
+        })
+    }
+
+    #[test]
+    fn test_per_message() {
+        let mut outbox = Outbox::new(Vec::new(), FlushPolicy::PerMessage);
+        outbox
+            .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        assert_eq!(outbox.get_ref(), &[0x41]);
+        outbox.send(&ticket()).unwrap();
+        assert_eq!(outbox.buffered(), 0);
+        assert_eq!(outbox.get_ref()[1..], codec::to_bytes(&ticket()).unwrap());
+    }
+
+    #[test]
+    fn test_per_tick() {
+        let mut outbox = Outbox::new(Vec::new(), FlushPolicy::PerTick);
+        outbox
+            .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        outbox.send(&ticket()).unwrap();
+        assert!(outbox.get_ref().is_empty());
+
+        outbox.tick().unwrap();
+        let expected =
+            codec::to_bytes(&(msg::OutgoingMessage::Heartbeat(msg::Heartbeat), ticket())).unwrap();
+        assert_eq!(outbox.get_ref(), &expected);
+    }
+
+    #[test]
+    fn test_threshold() {
+        let mut outbox = Outbox::new(Vec::new(), FlushPolicy::Threshold(3));
+        outbox
+            .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        outbox
+            .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        assert!(outbox.get_ref().is_empty());
+        outbox
+            .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        assert_eq!(outbox.get_ref(), &[0x41; 3]);
+
+        // Anything under the threshold still goes out on the next tick.
+        outbox
+            .send(&msg::OutgoingMessage::Heartbeat(msg::Heartbeat))
+            .unwrap();
+        assert_eq!(outbox.buffered(), 1);
+        outbox.tick().unwrap();
+        assert_eq!(outbox.get_ref(), &[0x41; 4]);
+    }
+
+    #[test]
+    fn test_delivered_after_flush() {
+        let mut outbox = Outbox::new(Vec::new(), FlushPolicy::PerTick);
+        outbox.send(&ticket()).unwrap();
+        assert!(outbox.take_delivered().is_empty());
+
+        outbox.tick().unwrap();
+        let delivered = outbox.take_delivered();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(msg::OutgoingMessage::Ticket(delivered[0].clone()), ticket());
+        assert!(outbox.take_delivered().is_empty());
+
+        // Tickets that never made it out aren't delivered.
+        outbox.send(&ticket()).unwrap();
+        assert_eq!(outbox.into_unflushed().len(), 1);
+    }
+
+    // A connection that takes so many bytes and then goes away.
+    struct Cutoff {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Cutoff {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.limit - self.written.len());
+            if n == 0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_partial_write() {
+        let len = codec::to_bytes(&ticket()).unwrap().len();
+        let writer = Cutoff {
+            written: Vec::new(),
+            limit: len + 3,
+        };
+        let mut outbox = Outbox::new(writer, FlushPolicy::PerTick);
+        outbox.send(&ticket()).unwrap();
+        outbox.send(&ticket()).unwrap();
+        assert!(outbox.tick().is_err());
+        assert_eq!(outbox.buffered(), len - 3);
+
+        // The first ticket went out whole, so only the second can be sent elsewhere.
+        assert_eq!(outbox.take_delivered().len(), 1);
+        assert_eq!(outbox.into_unflushed().len(), 1);
+    }
+
+    #[test]
+    fn test_capture_written_tickets() {
+        use crate::capture::{self, Capture, Entry};
+        use crate::fixtures::temp_path;
+
+        let path = temp_path("outbox");
+        let capture = Capture::create(&path).unwrap();
+        let broken = Cutoff {
+            written: Vec::new(),
+            limit: 0,
+        };
+        let mut outbox = Outbox::new(broken, FlushPolicy::PerTick);
+        outbox.set_capture(capture.tap());
+        outbox.send(&ticket()).unwrap();
+        assert!(outbox.tick().is_err());
+        let rerouted = outbox.into_unflushed();
+
+        // Only the dispatcher the ticket is re-routed to captures it.
+        let mut outbox = Outbox::new(Vec::new(), FlushPolicy::PerTick);
+        outbox.set_capture(capture.tap());
+        outbox
+            .send(&msg::OutgoingMessage::Ticket(rerouted[0].clone()))
+            .unwrap();
+        assert!(capture::read(&path).unwrap().is_empty());
+        outbox.tick().unwrap();
+        let entries = capture::read(&path).unwrap();
+        assert!(
+            matches!(&entries[..], [Entry::Outgoing(s, m)] if s.connection == 1 && *m == ticket())
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_error() {
+        let mut outbox = Outbox::new(Vec::new(), FlushPolicy::PerTick);
+        let msg = msg::OutgoingMessage::Error(msg::Error("x".repeat(256)));
+        assert!(outbox.send(&msg).is_err());
+        assert_eq!(outbox.buffered(), 0);
+
+        // The next message isn't appended to half of the one that failed.
+        outbox.send(&ticket()).unwrap();
+        outbox.tick().unwrap();
+        assert_eq!(outbox.get_ref(), &codec::to_bytes(&ticket()).unwrap());
+    }
+}
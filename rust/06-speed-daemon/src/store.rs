@@ -0,0 +1,241 @@
+use super::ticket::{Ledger, TicketEngine};
+use super::{codec, msg};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Everything that changes the server's state, in the order it happened. Each variant's index is
+/// its tag in the codec, so new variants go at the end.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Record {
+    /// A camera reported a plate.
+    Observation(msg::IAmCamera, msg::Plate),
+    /// A ticket was issued and entered in the ledger.
+    Issued(msg::Ticket),
+    /// A ticket was written out to a dispatcher.
+    Delivered(msg::Ticket),
+}
+
+/// Somewhere to keep records so the server's state survives a restart.
+pub trait Store {
+    /// Add a record to the end of the store.
+    fn append(&mut self, record: &Record) -> Result<(), Box<dyn Error>>;
+
+    /// Every record appended so far, oldest first.
+    fn records(&self) -> Result<Vec<Record>, Box<dyn Error>>;
+}
+
+pub type SharedStore = Arc<Mutex<dyn Store + Send>>;
+
+/// Throws every record away, for when nothing needs to survive a restart.
+#[derive(Debug, Default)]
+pub struct NullStore;
+
+impl Store for NullStore {
+    fn append(&mut self, _: &Record) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn records(&self) -> Result<Vec<Record>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Keeps records in memory, so nothing survives a restart.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    records: Vec<Record>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn append(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        self.records.push(record.clone());
+        Ok(())
+    }
+
+    fn records(&self) -> Result<Vec<Record>, Box<dyn Error>> {
+        Ok(self.records.clone())
+    }
+}
+
+/// Appends records to a file in the codec's binary format. Records are written whole but not
+/// synced, so they survive the process going away but not necessarily the machine.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    file: File,
+}
+
+impl FileStore {
+    /// Open the store at the path, creating it if needed. A record left half written by a crash
+    /// is dropped so new records don't end up appended to it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let (_, partial) = decode(&bytes)?;
+        if partial > 0 {
+            file.set_len((bytes.len() - partial) as u64)?;
+        }
+        Ok(Self { path, file })
+    }
+}
+
+impl Store for FileStore {
+    fn append(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        // Encode first so the record goes out in a single write.
+        let bytes = codec::to_bytes(record)?;
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn records(&self) -> Result<Vec<Record>, Box<dyn Error>> {
+        Ok(decode(&fs::read(&self.path)?)?.0)
+    }
+}
+
+/// Decode every complete record, also returning how many bytes of a partial record were left.
+fn decode(bytes: &[u8]) -> codec::Result<(Vec<Record>, usize)> {
+    let mut decoder = codec::Decoder::new();
+    decoder.push(bytes);
+    let mut records = Vec::new();
+    while let Some(record) = decoder.decode()? {
+        records.push(record);
+    }
+    Ok((records, decoder.buffered()))
+}
+
+/// The state rebuilt by replaying a store's records.
+#[derive(Debug, Default)]
+pub struct Restored {
+    pub engine: TicketEngine,
+    pub ledger: Ledger,
+    /// Tickets that were issued but never written out to a dispatcher, oldest first.
+    pub pending: Vec<msg::Ticket>,
+}
+
+impl Restored {
+    pub fn from_records(records: impl IntoIterator<Item = Record>) -> Self {
+        let mut restored = Self::default();
+        for record in records {
+            match record {
+                // Any tickets these produce were already issued, and have their own records.
+                Record::Observation(camera, plate) => {
+                    restored.engine.observe(&camera, (&plate).into());
+                }
+                Record::Issued(ticket) => {
+                    restored.pending.extend(restored.ledger.issue(ticket));
+                }
+                Record::Delivered(ticket) => {
+                    if let Some(i) = restored.pending.iter().position(|t| *t == ticket) {
+                        restored.pending.remove(i);
+                    }
+                }
+            }
+        }
+        restored
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{camera, plate, temp_path, ticket};
+
+    #[test]
+    fn test_record_encoding() {
+        let record = Record::Observation(camera(8), plate(45));
+        let bytes = codec::to_bytes(&record).unwrap();
+        assert_eq!(bytes[0], 0);
+        assert_eq!(codec::from_bytes::<Record>(&bytes).unwrap(), record);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let records = vec![
+            Record::Observation(camera(0), plate(0)),
+            Record::Issued(ticket(0, 3600, 10000)),
+            Record::Issued(ticket(86400, 86500, 10000)),
+            Record::Delivered(ticket(0, 3600, 10000)),
+        ];
+        let mut store = MemoryStore::new();
+        assert!(store.records().unwrap().is_empty());
+        for record in &records {
+            store.append(record).unwrap();
+        }
+        assert_eq!(store.records().unwrap(), records);
+
+        let restored = Restored::from_records(store.records().unwrap());
+        assert_eq!(restored.pending, vec![ticket(86400, 86500, 10000)]);
+    }
+
+    #[test]
+    fn test_file_store_reopen() {
+        let path = temp_path("reopen");
+        let records = vec![
+            Record::Observation(camera(0), plate(0)),
+            Record::Issued(ticket(0, 3600, 10000)),
+            Record::Delivered(ticket(0, 3600, 10000)),
+        ];
+        let mut store = FileStore::open(&path).unwrap();
+        for record in &records {
+            store.append(record).unwrap();
+        }
+        assert_eq!(store.records().unwrap(), records);
+        drop(store);
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(store.records().unwrap(), records);
+        store.append(&records[0]).unwrap();
+        assert_eq!(store.records().unwrap().len(), 4);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_partial_record() {
+        let path = temp_path("partial");
+        let record = Record::Issued(ticket(0, 3600, 10000));
+        let mut bytes = codec::to_bytes(&record).unwrap();
+        let len = bytes.len();
+        bytes.extend_from_slice(&codec::to_bytes(&record).unwrap()[..len - 1]);
+        fs::write(&path, bytes).unwrap();
+
+        // The partial record is dropped rather than having the next one appended to it.
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(store.records().unwrap(), vec![record.clone()]);
+        store.append(&record).unwrap();
+        assert_eq!(store.records().unwrap(), vec![record.clone(), record]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_restore() {
+        let records = vec![
+            Record::Observation(camera(0), plate(0)),
+            Record::Issued(ticket(0, 3600, 10000)),
+            Record::Issued(ticket(86400, 86500, 10000)),
+            Record::Delivered(ticket(0, 3600, 10000)),
+        ];
+        let mut restored = Restored::from_records(records);
+        assert_eq!(restored.pending, vec![ticket(86400, 86500, 10000)]);
+
+        // Days that were ticketed before the restart stay ticketed.
+        assert!(restored.ledger.issue(ticket(100, 200, 10000)).is_none());
+        // Observations are remembered, so a later sighting still gets a ticket.
+        let tickets = restored.engine.observe(&camera(100), (&plate(3600)).into());
+        assert_eq!(tickets.len(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+use super::msg;
+use std::collections::{HashMap, HashSet};
+
+const SECONDS_PER_DAY: u32 = 86400;
+
+/// A plate sighting along with the position and limit of the camera that reported it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Observation {
+    mile: u16,
+    limit: u16,
+    timestamp: u32,
+}
+
+/// Computes speeding violations from plate observations. This is purely in-memory bookkeeping
+/// and knows nothing about clients or sockets.
+#[derive(Debug, Default)]
+pub struct TicketEngine {
+    // Observations by road and then plate, so plates can be looked up without allocating.
+    observations: HashMap<u16, HashMap<String, Vec<Observation>>>,
+}
+
+impl TicketEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a plate reported by a camera and return a ticket for every previous observation of
+    /// the same plate on the same road where the average speed between the two was over the
+    /// limit.
+    pub fn observe(&mut self, camera: &msg::IAmCamera, plate: msg::PlateRef) -> Vec<msg::Ticket> {
+        let new = Observation {
+            mile: camera.mile,
+            limit: camera.limit,
+            timestamp: plate.timestamp,
+        };
+        let plates = self.observations.entry(camera.road).or_default();
+        let previous = plates.get(plate.plate).map_or(&[][..], Vec::as_slice);
+
+        let tickets = previous
+            .iter()
+            .filter_map(|old| {
+                let (first, second) = if old.timestamp < new.timestamp {
+                    (old, &new)
+                } else {
+                    (&new, old)
+                };
+                let speed = average_speed(
+                    (first.mile, first.timestamp),
+                    (second.mile, second.timestamp),
+                )?;
+                let limit = first.limit.min(second.limit);
+                over_limit(speed, limit).then_some((first, second, speed))
+            })
+            .map(|(first, second, speed)| msg::Ticket {
+                plate: plate.plate.to_string(),
+                road: camera.road,
+                mile1: first.mile,
+                timestamp1: first.timestamp,
+                mile2: second.mile,
+                timestamp2: second.timestamp,
+                speed,
+            })
+            .collect::<Vec<_>>();
+        match plates.get_mut(plate.plate) {
+            Some(observations) => observations.push(new),
+            None => {
+                plates.insert(plate.plate.to_string(), vec![new]);
+            }
+        }
+        tickets
+    }
+}
+
+/// Tracks which days each plate has been ticketed for, enforcing that a plate receives at most
+/// one ticket per day. A ticket spanning multiple days counts against all of them.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    days: HashMap<String, HashSet<u32>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the ticket as issued and return it, unless the plate has already been ticketed on
+    /// any of the days it covers, in which case it is dropped.
+    pub fn issue(&mut self, ticket: msg::Ticket) -> Option<msg::Ticket> {
+        let days = day(ticket.timestamp1)..=day(ticket.timestamp2);
+        let ticketed = self.days.entry(ticket.plate.clone()).or_default();
+        if days.clone().any(|day| ticketed.contains(&day)) {
+            return None;
+        }
+        ticketed.extend(days);
+        Some(ticket)
+    }
+}
+
+/// The day a timestamp falls on.
+pub fn day(timestamp: u32) -> u32 {
+    timestamp / SECONDS_PER_DAY
+}
+
+/// Calculate the average speed between two `(mile, timestamp)` observations in hundredths of a
+/// mile per hour, saturating at the max that fits in a ticket. Returns None unless the second
+/// observation happened after the first.
+pub fn average_speed(
+    (mile1, timestamp1): (u16, u32),
+    (mile2, timestamp2): (u16, u32),
+) -> Option<u16> {
+    let seconds = timestamp2.checked_sub(timestamp1).filter(|s| *s > 0)? as u64;
+    let miles = mile1.abs_diff(mile2) as u64;
+    // Round to the nearest hundredth.
+    let speed = (miles * 3600 * 100 + seconds / 2) / seconds;
+    Some(speed.min(u16::MAX as u64) as u16)
+}
+
+/// Whether a speed in hundredths of a mile per hour deserves a ticket on a road with the limit
+/// in miles per hour, which it does when it exceeds the limit by 0.5 mph or more.
+pub fn over_limit(speed: u16, limit: u16) -> bool {
+    speed as u32 >= limit as u32 * 100 + 50
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn camera(road: u16, mile: u16, limit: u16) -> msg::IAmCamera {
+        msg::IAmCamera { road, mile, limit }
+    }
+
+    fn plate(plate: &str, timestamp: u32) -> msg::PlateRef<'_> {
+        msg::PlateRef { plate, timestamp }
+    }
+
+    #[test]
+    fn test_speeding() {
+        let mut engine = TicketEngine::new();
+        assert!(engine
+            .observe(&camera(123, 8, 60), plate("UN1X", 0))
+            .is_empty());
+        let tickets = engine.observe(&camera(123, 9, 60), plate("UN1X", 45));
+        assert_eq!(
+            tickets,
+            vec![msg::Ticket {
+                plate: "UN1X".to_string(),
+                road: 123,
+                mile1: 8,
+                timestamp1: 0,
+                mile2: 9,
+                timestamp2: 45,
+                speed: 8000,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut engine = TicketEngine::new();
+        assert!(engine
+            .observe(&camera(123, 9, 60), plate("UN1X", 45))
+            .is_empty());
+        let tickets = engine.observe(&camera(123, 8, 60), plate("UN1X", 0));
+        assert_eq!(tickets.len(), 1);
+        assert_eq!((tickets[0].mile1, tickets[0].timestamp1), (8, 0));
+        assert_eq!((tickets[0].mile2, tickets[0].timestamp2), (9, 45));
+        assert_eq!(tickets[0].speed, 8000);
+    }
+
+    #[test]
+    fn test_limit_tolerance() {
+        // 121 miles in 2 hours is 60.5 mph, which is just enough for a ticket.
+        let mut engine = TicketEngine::new();
+        engine.observe(&camera(1, 0, 60), plate("A", 0));
+        let tickets = engine.observe(&camera(1, 121, 60), plate("A", 7200));
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(tickets[0].speed, 6050);
+
+        // 120 miles in 2 hours is exactly the limit.
+        let mut engine = TicketEngine::new();
+        engine.observe(&camera(1, 0, 60), plate("A", 0));
+        assert!(engine
+            .observe(&camera(1, 120, 60), plate("A", 7200))
+            .is_empty());
+
+        // 60.49 mph is still under the tolerance.
+        let mut engine = TicketEngine::new();
+        engine.observe(&camera(1, 0, 60), plate("A", 0));
+        assert!(engine
+            .observe(&camera(1, 6049, 60), plate("A", 360000))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_average_speed() {
+        assert_eq!(average_speed((8, 0), (9, 45)), Some(8000));
+        assert_eq!(average_speed((9, 0), (8, 45)), Some(8000));
+        // Rounded to the nearest hundredth.
+        assert_eq!(average_speed((0, 0), (1, 7)), Some(51429));
+        assert_eq!(average_speed((0, 0), (1000, 1)), Some(u16::MAX));
+        assert_eq!(average_speed((0, 45), (1, 0)), None);
+        assert_eq!(average_speed((0, 0), (1, 0)), None);
+    }
+
+    #[test]
+    fn test_separate_roads_and_plates() {
+        let mut engine = TicketEngine::new();
+        engine.observe(&camera(1, 0, 60), plate("A", 0));
+        assert!(engine
+            .observe(&camera(2, 10, 60), plate("A", 60))
+            .is_empty());
+        assert!(engine
+            .observe(&camera(1, 10, 60), plate("B", 60))
+            .is_empty());
+        assert_eq!(engine.observe(&camera(1, 10, 60), plate("A", 60)).len(), 1);
+    }
+
+    #[test]
+    fn test_every_pair() {
+        let mut engine = TicketEngine::new();
+        engine.observe(&camera(1, 0, 60), plate("A", 0));
+        engine.observe(&camera(1, 1000, 60), plate("A", 100000));
+        // Speeding relative to both previous observations.
+        let tickets = engine.observe(&camera(1, 2000, 60), plate("A", 100060));
+        assert_eq!(tickets.len(), 2);
+        assert_eq!(tickets[0].speed, 7196);
+        assert_eq!(tickets[1].speed, u16::MAX);
+    }
+
+    fn ticket(plate: &str, timestamp1: u32, timestamp2: u32) -> msg::Ticket {
+        msg::Ticket {
+            plate: plate.to_string(),
+            road: 1,
+            mile1: 0,
+            timestamp1,
+            mile2: 100,
+            timestamp2,
+            speed: 10000,
+        }
+    }
+
+    #[test]
+    fn test_ledger_one_per_day() {
+        let mut ledger = Ledger::new();
+        assert!(ledger.issue(ticket("A", 0, 100)).is_some());
+        assert!(ledger.issue(ticket("A", 200, 300)).is_none());
+        // Other plates are unaffected.
+        assert!(ledger.issue(ticket("B", 200, 300)).is_some());
+        // The next day is fine.
+        assert!(ledger
+            .issue(ticket("A", SECONDS_PER_DAY, SECONDS_PER_DAY + 100))
+            .is_some());
+    }
+
+    #[test]
+    fn test_ledger_multi_day() {
+        let mut ledger = Ledger::new();
+        // Covers days 1 through 3.
+        assert!(ledger
+            .issue(ticket("A", SECONDS_PER_DAY + 10, 3 * SECONDS_PER_DAY + 10))
+            .is_some());
+        // Day 2 is covered by the previous ticket.
+        assert!(ledger
+            .issue(ticket("A", 2 * SECONDS_PER_DAY, 2 * SECONDS_PER_DAY + 10))
+            .is_none());
+        // Overlaps on day 1.
+        assert!(ledger
+            .issue(ticket("A", SECONDS_PER_DAY - 10, SECONDS_PER_DAY + 1))
+            .is_none());
+        // Days 0 and 4 are still available.
+        assert!(ledger.issue(ticket("A", 0, 10)).is_some());
+        assert!(ledger
+            .issue(ticket("A", 4 * SECONDS_PER_DAY, 4 * SECONDS_PER_DAY + 1))
+            .is_some());
+    }
+
+    #[test]
+    fn test_ledger_rejected_ticket_is_not_recorded() {
+        let mut ledger = Ledger::new();
+        assert!(ledger.issue(ticket("A", 0, 10)).is_some());
+        // Spans days 0 and 1, but day 0 was taken so day 1 must stay free.
+        assert!(ledger
+            .issue(ticket("A", 20, SECONDS_PER_DAY + 10))
+            .is_none());
+        assert!(ledger
+            .issue(ticket("A", SECONDS_PER_DAY + 20, SECONDS_PER_DAY + 30))
+            .is_some());
+    }
+
+    #[test]
+    fn test_ledger_out_of_order_observations() {
+        let mut engine = TicketEngine::new();
+        let mut ledger = Ledger::new();
+        let mut issued = Vec::new();
+        // Observations arrive out of order, each pair speeding.
+        for (mile, timestamp) in [(20, 2000), (0, 0), (10, 1000)] {
+            issued.extend(
+                engine
+                    .observe(&camera(1, mile, 10), plate("A", timestamp))
+                    .into_iter()
+                    .filter_map(|ticket| ledger.issue(ticket)),
+            );
+        }
+        // All observations are on day 0, so only the first ticket survives.
+        assert_eq!(issued.len(), 1);
+        assert_eq!((issued[0].timestamp1, issued[0].timestamp2), (0, 2000));
+    }
+
+    #[test]
+    fn test_same_timestamp() {
+        let mut engine = TicketEngine::new();
+        engine.observe(&camera(1, 0, 60), plate("A", 0));
+        assert!(engine.observe(&camera(1, 10, 60), plate("A", 0)).is_empty());
+    }
+}
@@ -0,0 +1,6 @@
+// The derives' errors, each checked against the message the compiler gives for it.
+#[test]
+fn test_derive_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
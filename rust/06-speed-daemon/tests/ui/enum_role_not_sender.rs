@@ -0,0 +1,12 @@
+use speed_daemon::msg::{IAmDispatcher, IncomingMessage, Plate};
+use speed_daemon_derive::MessageEnum;
+
+// Only unidentified clients may send IAmDispatcher, so a camera can't.
+#[derive(MessageEnum)]
+#[message(role = camera, subset_of = IncomingMessage)]
+pub enum CameraIncoming {
+    Plate(Plate),
+    IAmDispatcher(IAmDispatcher),
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use speed_daemon::msg::Plate;
+use speed_daemon_derive::MessageEnum;
+
+#[derive(MessageEnum)]
+#[message(role = camera, superset = Plate)]
+pub enum CameraIncoming {
+    Plate(Plate),
+}
+
+fn main() {}
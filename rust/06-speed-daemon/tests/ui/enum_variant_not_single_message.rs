@@ -0,0 +1,10 @@
+use speed_daemon::msg::{Heartbeat, Plate};
+use speed_daemon_derive::MessageEnum;
+
+#[derive(MessageEnum)]
+pub enum Messages {
+    Plate(Plate),
+    Heartbeat(Heartbeat, Heartbeat),
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use speed_daemon_derive::Message;
+
+#[derive(Message)]
+#[message(id = 0x99, like = speed_daemon::msg::Plate)]
+pub struct Unknown;
+
+fn main() {}
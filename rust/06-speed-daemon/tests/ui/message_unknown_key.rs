@@ -0,0 +1,7 @@
+use speed_daemon_derive::Message;
+
+#[derive(Message)]
+#[message(id = 0x99, size = 4)]
+pub struct Unknown;
+
+fn main() {}